@@ -0,0 +1,74 @@
+use rustc_hash::FxHashMap;
+use std::{fmt::Display, str::FromStr};
+
+/// Extra arguments passed to a challenge after its day and part, e.g. `21a 6 10 seed=42`.
+/// Arguments of the form `name=value` are named, the rest are positional.
+#[derive(Default)]
+pub struct Args {
+    positional: Vec<String>,
+    named: FxHashMap<String, String>,
+}
+
+impl Args {
+    pub fn parse(input: &str) -> Self {
+        let mut args = Self::default();
+
+        for arg in input.split_ascii_whitespace() {
+            match arg.split_once('=') {
+                Some((name, value)) => {
+                    args.named.insert(name.to_string(), value.to_string());
+                }
+                None => args.positional.push(arg.to_string()),
+            }
+        }
+
+        args
+    }
+
+    /// Parses all positional arguments
+    pub fn positional<T>(&self) -> anyhow::Result<Vec<T>>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.positional
+            .iter()
+            .map(|value| parse_value("argument", value))
+            .collect()
+    }
+
+    /// Checks whether the name was passed as a positional argument
+    pub fn flag(&self, name: &str) -> bool {
+        self.positional.iter().any(|value| value == name)
+    }
+
+    /// Parses the named argument if it was passed
+    pub fn get<T>(&self, name: &str) -> anyhow::Result<Option<T>>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.named
+            .get(name)
+            .map(|value| parse_value(name, value))
+            .transpose()
+    }
+
+    pub fn get_or<T>(&self, name: &str, default: T) -> anyhow::Result<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        Ok(self.get(name)?.unwrap_or(default))
+    }
+}
+
+fn parse_value<T>(name: &str, value: &str) -> anyhow::Result<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value
+        .parse()
+        .map_err(|err| anyhow::anyhow!("Invalid {name} '{value}': {err}"))
+}
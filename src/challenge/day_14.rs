@@ -1,3 +1,4 @@
+use crate::utils::cycle;
 use std::ops::{Index, IndexMut};
 
 const CYCLE_COUNT: usize = 1000000000;
@@ -26,51 +27,12 @@ pub fn part_a(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
 }
 
 pub fn part_b(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
-    let mut grid = Grid::new(input);
-
     // There aren't that many possible arangements, so the layouts start to loop.
-    // Coincidently the loop can be observed by looking at the total loads on the north beam.
-    let mut loads = Vec::with_capacity(200);
-
-    // Blindly compute a few cycles as the first few are unlikely to contain a loop.
-    // If we compute to many, we'll end up wasting time as simulating the cycle is very expensive.
-    loads.extend((0..150).map(|_| grid.cycle()));
-
-    let position = loop {
-        match find_loop(&loads) {
-            Some(position) => break position,
-            None => loads.push(grid.cycle()),
-        }
-    };
-
-    let loop_length = loads.len() - position;
-    let loop_offset = (CYCLE_COUNT - loads.len()) % loop_length;
-    Ok(loads[position + loop_offset - 1])
-}
-
-fn find_loop(values: &[usize]) -> Option<usize> {
-    let last = *values.last().unwrap();
-
-    // Iterate over the values in reverse, skipping the latest one.
-    // If `x` is the current value and `z` is the latest value, check if `a, b, c, ...` are the same.
-    // [..., a, b, c, d, e, x, a, b, c, d, e, z]
-    // If they are, assume we found a loop and return the current position.
-    values
-        .iter()
-        .enumerate()
-        .rev()
-        .skip(1)
-        .filter(|(_, value)| **value == last)
-        .map(|(i, _)| i + 1)
-        .find(|&i| {
-            // i is the potnetial position of the start of the next iteration of the loop
-            let first = &values[i..values.len() - 1];
-            let second = &values[2 * i - values.len()..i - 1];
-            // first and second contain the current and next instance of the loop
-            first == second
-        })
+    let grid = cycle::state_after(Grid::new(input), CYCLE_COUNT, Grid::cycle);
+    Ok(grid.total_load())
 }
 
+#[derive(Clone, Eq, PartialEq, Hash)]
 struct Grid {
     width: usize,
     height: usize,
@@ -95,12 +57,11 @@ impl Grid {
         }
     }
 
-    fn cycle(&mut self) -> usize {
+    fn cycle(&mut self) {
         self.tilt_north();
         self.tilt_west();
         self.tilt_south();
         self.tilt_east();
-        self.total_load()
     }
 
     fn tilt_north(&mut self) {
@@ -200,7 +161,7 @@ impl IndexMut<(usize, usize)> for Grid {
     }
 }
 
-#[derive(Copy, Clone, Eq, PartialEq)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash)]
 enum Cell {
     Empty,
     Fixed,
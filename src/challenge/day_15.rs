@@ -1,61 +1,102 @@
-use crate::utils::Bytes;
+use crate::{args::Args, utils::Bytes};
+
+// Lenses in each box of the fast path
+const SLOTS: usize = 6;
+// Longest label which can be packed into an id by the fast path
+const MAX_ID_LENGTH: usize = 8;
 
 pub fn part_a(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
-    Ok(input[0]
+    Ok(sequence(input)?
         .split(',')
         .map(|step| hash(step.as_bytes()))
         .sum::<usize>())
 }
 
-pub fn part_b(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
+// Passing `trace` prints the contents of the boxes after every step, like the puzzle
+pub fn part_b(input: &[&str], args: &Args) -> anyhow::Result<impl std::fmt::Display> {
+    let steps = sequence(input)?
+        .split(',')
+        .map(Step::parse)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    if args.flag("trace") {
+        let mut boxes = Boxes::default();
+
+        for (step, input) in steps.iter().zip(sequence(input)?.split(',')) {
+            boxes.apply(step);
+            println!("After \"{input}\":\n{}", boxes.describe());
+        }
+
+        return Ok(boxes.focusing_power());
+    }
+
+    // the fast path gives up when a label or a box doesn't fit
+    if let Some(focusing_power) = fast_focusing_power(&steps) {
+        return Ok(focusing_power);
+    }
+
+    let mut boxes = Boxes::default();
+
+    for step in steps.iter() {
+        boxes.apply(step);
+    }
+
+    Ok(boxes.focusing_power())
+}
+
+// The initialization sequence is a single line
+fn sequence<'a>(input: &[&'a str]) -> anyhow::Result<&'a str> {
+    match input {
+        [sequence] if !sequence.is_empty() => Ok(sequence),
+        _ => anyhow::bail!("The initialization sequence must be a single, non-empty, line"),
+    }
+}
+
+// Returns `None` when a label is longer than 8 bytes or when a box needs more than 6 slots
+fn fast_focusing_power(steps: &[Step]) -> Option<usize> {
     // [Lense; 6] is allocated directly on the stack
     // so it's is faster than Vec<Lense>, but required more logic
-    let mut boxes = [[Lense::default(); 6]; 256];
+    let mut boxes = [[Lense::default(); SLOTS]; 256];
 
-    // .as_bytes().split() avoids the performance penalty to variable-width UTF-8 chars
-    for step in input[0].as_bytes().split(|char| *char == b',') {
-        let index = step
-            .iter()
-            .rposition(|char| !char.is_ascii_alphanumeric())
-            .unwrap();
-
-        let label = &step[..index];
-        let id = id(label);
-        let operation = step[index];
-
-        let slots = &mut boxes[hash(label)];
-
-        if operation == b'-' {
-            let index = match slots.iter().position(|lense| lense.id == id) {
-                Some(offset) => offset,
-                None => continue,
-            };
-
-            let next = index + 1;
-
-            let length = match slots[next..].iter().position(|lense| lense.id == 0) {
-                Some(offset) => next + offset,
-                None => slots.len(),
-            };
-
-            // shift the lenses and clear the last lense
-            if next < length {
-                // equal to slots[index..length].rotate_left(1)
-                // copy_within uses memmove which yield a small performance improvement
-                slots.copy_within(next..length, index);
-                slots[length - 1] = Lense::default();
-            } else {
-                slots[index] = Lense::default();
-            }
-        } else {
-            let focal_length = &step[index + 1..];
+    for step in steps {
+        if step.label.len() > MAX_ID_LENGTH {
+            return None;
+        }
+
+        let id = id(step.label);
+        let slots = &mut boxes[hash(step.label)];
+
+        match step.operation {
+            Operation::Remove => {
+                let index = match slots.iter().position(|lense| lense.id == id) {
+                    Some(offset) => offset,
+                    None => continue,
+                };
 
-            let lense = slots
-                .iter_mut()
-                .find(|lense| lense.id == id || lense.id == 0)
-                .unwrap();
+                let next = index + 1;
 
-            *lense = Lense { id, focal_length };
+                let length = match slots[next..].iter().position(|lense| lense.id == 0) {
+                    Some(offset) => next + offset,
+                    None => slots.len(),
+                };
+
+                // shift the lenses and clear the last lense
+                if next < length {
+                    // equal to slots[index..length].rotate_left(1)
+                    // copy_within uses memmove which yield a small performance improvement
+                    slots.copy_within(next..length, index);
+                    slots[length - 1] = Lense::default();
+                } else {
+                    slots[index] = Lense::default();
+                }
+            }
+            Operation::Insert(focal_length) => {
+                let lense = slots
+                    .iter_mut()
+                    .find(|lense| lense.id == id || lense.id == 0)?;
+
+                *lense = Lense { id, focal_length };
+            }
         }
     }
 
@@ -68,17 +109,17 @@ pub fn part_b(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
             // skipping over empty slots doesn't yield a performance improvement due to the additional branches
             slots.into_iter().enumerate().map(move |(i, lense)| {
                 let slot_multiplier = i + 1;
-                lense.focal_length.parse_dec::<usize>() * slot_multiplier * box_multiplier
+                lense.focal_length * slot_multiplier * box_multiplier
             })
         })
         .sum::<usize>();
 
-    Ok(focusing_power)
+    Some(focusing_power)
 }
 
 // assumes the label isn't longer than 8 bytes
 fn id(value: &[u8]) -> u64 {
-    let mut bytes = [0u8; 8];
+    let mut bytes = [0u8; MAX_ID_LENGTH];
     bytes[0..value.len()].copy_from_slice(value);
     u64::from_ne_bytes(bytes)
 }
@@ -89,8 +130,203 @@ fn hash(value: &[u8]) -> usize {
         .fold(0, |acc, char| (acc + *char as usize) * 17 % 256)
 }
 
+struct Step<'a> {
+    label: &'a [u8],
+    operation: Operation,
+}
+
+impl<'a> Step<'a> {
+    // parse "rn=1" or "cm-"
+    fn parse(input: &'a str) -> anyhow::Result<Self> {
+        let bytes = input.as_bytes();
+        let index = bytes
+            .iter()
+            .position(|char| !char.is_ascii_alphanumeric())
+            .unwrap_or(bytes.len());
+
+        let label = &bytes[..index];
+
+        let operation = match &bytes[index..] {
+            b"-" => Some(Operation::Remove),
+            [b'=', focal_length @ ..]
+                if !focal_length.is_empty()
+                    && focal_length.len() < 16
+                    && focal_length.iter().all(u8::is_ascii_digit) =>
+            {
+                Some(Operation::Insert(focal_length.parse_dec()))
+            }
+            _ => None,
+        };
+
+        match operation {
+            Some(operation) if !label.is_empty() => Ok(Self { label, operation }),
+            _ => anyhow::bail!(
+                "Invalid step '{input}', expected a label followed by - or by = and a focal length"
+            ),
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+enum Operation {
+    Remove,
+    Insert(usize),
+}
+
 #[derive(Default, Copy, Clone)]
-struct Lense<'a> {
+struct Lense {
     id: u64,
-    focal_length: &'a [u8],
+    focal_length: usize,
+}
+
+// Boxes holding any number of lenses, with labels of any length
+struct Boxes<'a> {
+    boxes: Vec<Vec<(&'a [u8], usize)>>,
+}
+
+impl Default for Boxes<'_> {
+    fn default() -> Self {
+        Self {
+            boxes: vec![Vec::new(); 256],
+        }
+    }
+}
+
+impl<'a> Boxes<'a> {
+    fn apply(&mut self, step: &Step<'a>) {
+        let lenses = &mut self.boxes[hash(step.label)];
+        let index = lenses.iter().position(|(label, _)| *label == step.label);
+
+        match (step.operation, index) {
+            (Operation::Remove, Some(index)) => {
+                lenses.remove(index);
+            }
+            (Operation::Remove, None) => {}
+            (Operation::Insert(focal_length), Some(index)) => lenses[index].1 = focal_length,
+            (Operation::Insert(focal_length), None) => lenses.push((step.label, focal_length)),
+        }
+    }
+
+    fn focusing_power(&self) -> usize {
+        self.boxes
+            .iter()
+            .enumerate()
+            .flat_map(|(i, lenses)| {
+                lenses
+                    .iter()
+                    .enumerate()
+                    .map(move |(slot, (_, focal_length))| (i + 1) * (slot + 1) * focal_length)
+            })
+            .sum()
+    }
+
+    // Formats the boxes which aren't empty the same way as the puzzle, e.g. "Box 0: [rn 1] [cm 2]"
+    fn describe(&self) -> String {
+        let mut lines = Vec::new();
+
+        for (i, lenses) in self.boxes.iter().enumerate() {
+            if lenses.is_empty() {
+                continue;
+            }
+
+            let lenses = lenses
+                .iter()
+                .map(|(label, focal_length)| {
+                    format!("[{} {focal_length}]", String::from_utf8_lossy(label))
+                })
+                .collect::<Vec<_>>();
+
+            lines.push(format!("Box {i}: {}", lenses.join(" ")));
+        }
+
+        lines.push(String::new());
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &[&str] = &["rn=1,cm-,qp=3,cm=2,qp-,pc=4,ot=9,ab=5,pc-,pc=6,ot=7"];
+
+    fn steps(sequence: &str) -> Vec<Step<'_>> {
+        sequence
+            .split(',')
+            .map(|step| Step::parse(step).unwrap())
+            .collect()
+    }
+
+    fn boxes<'a>(steps: &[Step<'a>]) -> Boxes<'a> {
+        let mut boxes = Boxes::default();
+
+        for step in steps {
+            boxes.apply(step);
+        }
+
+        boxes
+    }
+
+    #[test]
+    fn example() {
+        assert_eq!(part_a(EXAMPLE).unwrap().to_string(), "1320");
+        assert_eq!(
+            part_b(EXAMPLE, &Args::default()).unwrap().to_string(),
+            "145"
+        );
+
+        let steps = steps(EXAMPLE[0]);
+        assert_eq!(fast_focusing_power(&steps), Some(145));
+
+        assert_eq!(
+            boxes(&steps).describe(),
+            "Box 0: [rn 1] [cm 2]\nBox 3: [ot 7] [ab 5] [pc 6]\n"
+        );
+    }
+
+    #[test]
+    fn box_with_seven_lenses() {
+        // all these labels hash to box 0
+        let sequence = "aju=1,akd=2,ayv=3,aze=4,bhv=5,bie=6,bww=7";
+        let steps = steps(sequence);
+
+        assert_eq!(fast_focusing_power(&steps[..6]), Some(91));
+        assert_eq!(fast_focusing_power(&steps), None);
+        assert_eq!(boxes(&steps).focusing_power(), 140);
+        assert_eq!(
+            part_b(&[sequence], &Args::default()).unwrap().to_string(),
+            "140"
+        );
+    }
+
+    #[test]
+    fn label_longer_than_an_id() {
+        let sequence = "rn=1,abcdefghi=3,abcdefgh=2";
+        let steps = steps(sequence);
+
+        assert_eq!(fast_focusing_power(&steps[..1]), Some(1));
+        assert_eq!(fast_focusing_power(&steps), None);
+        // "abcdefghi" goes in box 221 and "abcdefgh" in box 164
+        assert_eq!(boxes(&steps).focusing_power(), 1 + 222 * 3 + 165 * 2);
+        assert_eq!(
+            part_b(&[sequence], &Args::default()).unwrap().to_string(),
+            "997"
+        );
+    }
+
+    #[test]
+    fn invalid_steps() {
+        for step in ["cm", "=1", "rn=", "rn=x", "rn-1", "rn=1-"] {
+            let error = part_b(&[step], &Args::default()).err().unwrap();
+
+            assert_eq!(
+                error.to_string(),
+                format!(
+                    "Invalid step '{step}', expected a label followed by - or by = and a focal length"
+                )
+            );
+        }
+
+        assert!(part_b(&[], &Args::default()).is_err());
+    }
 }
@@ -1,35 +1,73 @@
-pub fn part_a(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
-    let mut grid = Grid::new(input);
-    grid.fire_beam((0, 0), Direction::RIGHT);
-    Ok(grid.energized)
-}
+use crate::{
+    args::Args,
+    utils::{graph, BitSet},
+};
+
+// Passing `x=<x>`, `y=<y>` and `direction=<up|down|left|right>` fires the beam from another tile
+// and `render` prints the beams and the energized tiles the same way as the puzzle
+pub fn part_a(input: &[&str], args: &Args) -> anyhow::Result<impl std::fmt::Display> {
+    let graph = BeamGraph::new(Grid::new(input));
+    let position = (args.get_or("x", 0)?, args.get_or("y", 0)?);
 
-pub fn part_b(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
-    let mut grid = Grid::new(input);
-    let initial_cells = grid.cells.clone();
-    let mut best = 0;
+    let direction = match args.get::<String>("direction")?.as_deref() {
+        None | Some("right") => Direction::RIGHT,
+        Some("left") => Direction::LEFT,
+        Some("up") => Direction::UP,
+        Some("down") => Direction::DOWN,
+        Some(direction) => {
+            anyhow::bail!("Invalid direction {direction}, expected up, down, left or right")
+        }
+    };
 
-    for x in 0..grid.width {
-        grid.reset(&initial_cells);
-        grid.fire_beam((x, 0), Direction::DOWN);
-        best = best.max(grid.energized);
+    if position.0 >= graph.grid.width || position.1 >= graph.grid.height {
+        anyhow::bail!(
+            "({}, {}) is outside of the {}x{} grid",
+            position.0,
+            position.1,
+            graph.grid.width,
+            graph.grid.height
+        );
+    }
 
-        grid.reset(&initial_cells);
-        grid.fire_beam((x, grid.max_y), Direction::UP);
-        best = best.max(grid.energized);
+    if args.flag("render") {
+        println!("{}", graph.grid.render(position, direction));
     }
 
-    for y in 0..grid.height {
-        grid.reset(&initial_cells);
-        grid.fire_beam((0, y), Direction::RIGHT);
-        best = best.max(grid.energized);
+    Ok(graph.energized(position, direction))
+}
+
+// Every beam ends up being split by the same splitters, so the tiles energized after reaching
+// each splitter are computed once (see `BeamGraph`) and shared by all the entry points
+//
+// Passing `report` adds the entry point energizing the most tiles to the result and `render`
+// prints its beams
+pub fn part_b(input: &[&str], args: &Args) -> anyhow::Result<impl std::fmt::Display> {
+    let graph = BeamGraph::new(Grid::new(input));
+
+    let best = graph
+        .grid
+        .entry_points()
+        .map(|(position, direction)| (graph.energized(position, direction), position, direction))
+        .max_by_key(|(energized, ..)| *energized);
+
+    let Some((energized, position, direction)) = best else {
+        anyhow::bail!("The grid has no entry points");
+    };
 
-        grid.reset(&initial_cells);
-        grid.fire_beam((grid.max_x, y), Direction::LEFT);
-        best = best.max(grid.energized);
+    if args.flag("render") {
+        println!("{}", graph.grid.render(position, direction));
     }
 
-    Ok(best)
+    if !args.flag("report") {
+        return Ok(energized.to_string());
+    }
+
+    Ok(format!(
+        "{energized} (entering ({}, {}) moving {})",
+        position.0,
+        position.1,
+        direction.name()
+    ))
 }
 
 struct Grid {
@@ -37,8 +75,7 @@ struct Grid {
     height: usize,
     max_x: usize,
     max_y: usize,
-    cells: Vec<Cell>,
-    energized: usize,
+    tiles: Vec<Tile>,
 }
 
 impl Grid {
@@ -46,10 +83,10 @@ impl Grid {
         let width = input[0].len();
         let height = input.len();
 
-        let cells = input
+        let tiles = input
             .iter()
             .flat_map(|row| row.bytes())
-            .map(Cell::from)
+            .map(Tile::from)
             .collect();
 
         Self {
@@ -57,60 +94,129 @@ impl Grid {
             height,
             max_x: width - 1,
             max_y: height - 1,
-            cells,
-            energized: 0,
+            tiles,
         }
     }
 
-    fn fire_beam(&mut self, mut position: (usize, usize), mut direction: Direction) {
+    // Positions and directions of beams entering from each of the edges
+    fn entry_points(&self) -> impl Iterator<Item = ((usize, usize), Direction)> + '_ {
+        let columns = (0..self.width)
+            .flat_map(|x| [((x, 0), Direction::DOWN), ((x, self.max_y), Direction::UP)]);
+
+        let rows = (0..self.height).flat_map(|y| {
+            [
+                ((0, y), Direction::RIGHT),
+                ((self.max_x, y), Direction::LEFT),
+            ]
+        });
+
+        columns.chain(rows)
+    }
+
+    // Follows a beam entering the tile at the position, energizing every tile it passes through,
+    // until it leaves the grid, loops back onto itself or reaches a splitter which splits it.
+    // Returns the index of that splitter.
+    fn trace(
+        &self,
+        position: (usize, usize),
+        direction: Direction,
+        energized: &mut BitSet,
+    ) -> Option<usize> {
+        let start = (position, direction);
+        let (mut position, mut direction) = start;
+
         loop {
-            let cell = &mut self.cells[position.0 + position.1 * self.width];
-            let tile = cell.tile;
-            let flag = direction.to_flag();
-
-            if cell.state == 0 {
-                cell.state = flag;
-                self.energized += 1;
-            } else if cell.state & flag == 0 {
-                cell.state |= flag;
-            } else {
-                break;
+            let index = position.0 + position.1 * self.width;
+            energized.insert(index);
+
+            let tile = self.tiles[index];
+
+            if tile.splits(direction) {
+                return Some(index);
             }
 
-            direction = self.simulate_beam(position, direction, tile);
+            direction = match tile {
+                Tile::NormalMirror => direction.normal_mirror(),
+                Tile::ReverseMirror => direction.reverse_mirror(),
+                _ => direction,
+            };
 
-            position = match self.next_position(position, direction) {
-                Some(position) => position,
-                None => break,
+            position = self.next_position(position, direction)?;
+
+            // beams can only loop back onto the tile they started from, as every other tile
+            // has a single way of being entered in each direction without splitting
+            if (position, direction) == start {
+                return None;
             }
         }
     }
 
-    fn simulate_beam(
-        &mut self,
-        position: (usize, usize),
-        direction: Direction,
-        tile: Tile,
-    ) -> Direction {
-        match tile {
-            Tile::VerticalSplit if matches!(direction, Direction::LEFT | Direction::RIGHT) => {
-                if let Some(position) = self.next_position(position, Direction::UP) {
-                    self.fire_beam(position, Direction::UP)
-                }
+    // Follows every beam starting from the position, returning the directions in which beams
+    // enter each tile (as flags)
+    fn fire_beam(&self, position: (usize, usize), direction: Direction) -> Vec<u8> {
+        let mut beams = vec![0; self.tiles.len()];
+        let mut stack = vec![(position, direction)];
 
-                Direction::DOWN
+        while let Some((position, direction)) = stack.pop() {
+            let index = position.0 + position.1 * self.width;
+            let flag = 1 << direction.0;
+
+            if beams[index] & flag != 0 {
+                continue;
             }
-            Tile::HorizontalSplit if matches!(direction, Direction::UP | Direction::DOWN) => {
-                if let Some(position) = self.next_position(position, Direction::LEFT) {
-                    self.fire_beam(position, Direction::LEFT)
+
+            beams[index] |= flag;
+
+            let (first, second) = match self.tiles[index] {
+                Tile::VerticalSplit if self.tiles[index].splits(direction) => {
+                    (Direction::UP, Some(Direction::DOWN))
+                }
+                Tile::HorizontalSplit if self.tiles[index].splits(direction) => {
+                    (Direction::LEFT, Some(Direction::RIGHT))
                 }
+                Tile::NormalMirror => (direction.normal_mirror(), None),
+                Tile::ReverseMirror => (direction.reverse_mirror(), None),
+                _ => (direction, None),
+            };
+
+            for direction in std::iter::once(first).chain(second) {
+                if let Some(position) = self.next_position(position, direction) {
+                    stack.push((position, direction));
+                }
+            }
+        }
+
+        beams
+    }
+
+    // Draws the beams like the puzzle, with empty tiles showing the direction of the beam passing
+    // through them (or the number of beams), followed by the energized tiles
+    fn render(&self, position: (usize, usize), direction: Direction) -> String {
+        let beams = self.fire_beam(position, direction);
+        let mut output = String::new();
+
+        for row in self.tiles.chunks(self.width).zip(beams.chunks(self.width)) {
+            for (tile, beams) in row.0.iter().zip(row.1) {
+                output.push(match (tile, beams.count_ones()) {
+                    (Tile::Empty, 0) => '.',
+                    (Tile::Empty, 1) => Direction(beams.trailing_zeros() as u8).arrow(),
+                    (Tile::Empty, count) => char::from_digit(count, 10).unwrap(),
+                    (tile, _) => tile.symbol(),
+                });
+            }
+
+            output.push('\n');
+        }
 
-                Direction::RIGHT
+        for row in beams.chunks(self.width) {
+            output.push('\n');
+
+            for beams in row {
+                output.push(if *beams != 0 { '#' } else { '.' });
             }
-            Tile::NormalMirror => direction.normal_mirror(),
-            Tile::ReverseMirror => direction.reverse_mirror(),
-            _ => direction,
         }
+
+        output
     }
 
     fn next_position(
@@ -126,31 +232,111 @@ impl Grid {
             _ => None,
         }
     }
-
-    fn reset(&mut self, initial_cells: &[Cell]) {
-        self.cells.copy_from_slice(initial_cells);
-        self.energized = 0;
-    }
 }
 
-#[derive(Copy, Clone)]
-struct Cell {
-    tile: Tile,
-    state: u8,
+// Graph of the splitters, where each splitter is connected to the splitters its two beams end up
+// being split by. A splitter energizes the same tiles as all the splitters it can reach, so the
+// graph is condensed into its strongly connected components and the energized tiles of each
+// component are the union of its own tiles and the tiles of the components after it.
+struct BeamGraph {
+    grid: Grid,
+    // Node of each splitter tile
+    nodes: Vec<usize>,
+    // Component of each node
+    components: Vec<usize>,
+    // Tiles energized after reaching any of the splitters of each component
+    energized: Vec<BitSet>,
 }
 
-impl From<u8> for Cell {
-    fn from(value: u8) -> Self {
-        let tile = match value {
-            b'.' => Tile::Empty,
-            b'|' => Tile::VerticalSplit,
-            b'-' => Tile::HorizontalSplit,
-            b'/' => Tile::NormalMirror,
-            b'\\' => Tile::ReverseMirror,
-            _ => unreachable!(),
-        };
+impl BeamGraph {
+    fn new(grid: Grid) -> Self {
+        let mut nodes = vec![usize::MAX; grid.tiles.len()];
+        let mut splitters = Vec::new();
+
+        for (index, tile) in grid.tiles.iter().enumerate() {
+            if matches!(tile, Tile::VerticalSplit | Tile::HorizontalSplit) {
+                nodes[index] = splitters.len();
+                splitters.push(index);
+            }
+        }
+
+        // tiles energized by the beams of each splitter, until they're split again
+        let mut tiles = Vec::with_capacity(splitters.len());
+        let mut children = Vec::with_capacity(splitters.len());
+
+        for &index in splitters.iter() {
+            let position = (index % grid.width, index / grid.width);
+            let mut energized = BitSet::with_capacity(grid.tiles.len());
+            let mut next = Vec::with_capacity(2);
+            energized.insert(index);
+
+            let directions = match grid.tiles[index] {
+                Tile::VerticalSplit => [Direction::UP, Direction::DOWN],
+                _ => [Direction::LEFT, Direction::RIGHT],
+            };
+
+            for direction in directions {
+                let Some(position) = grid.next_position(position, direction) else {
+                    continue;
+                };
+
+                if let Some(splitter) = grid.trace(position, direction, &mut energized) {
+                    next.push(nodes[splitter]);
+                }
+            }
+
+            tiles.push(energized);
+            children.push(next);
+        }
+
+        let found = graph::strongly_connected_components(splitters.len(), |node| {
+            children[node].iter().copied()
+        });
+
+        let mut components = vec![0; splitters.len()];
+
+        for (component, members) in found.iter().enumerate() {
+            for &node in members {
+                components[node] = component;
+            }
+        }
+
+        // components are found after all the components they can reach
+        let mut energized: Vec<BitSet> = Vec::with_capacity(found.len());
+
+        for (component, members) in found.iter().enumerate() {
+            let mut union = BitSet::with_capacity(grid.tiles.len());
 
-        Self { tile, state: 0 }
+            for &node in members {
+                union |= &tiles[node];
+
+                for &child in children[node].iter() {
+                    if components[child] != component {
+                        union |= &energized[components[child]];
+                    }
+                }
+            }
+
+            energized.push(union);
+        }
+
+        Self {
+            grid,
+            nodes,
+            components,
+            energized,
+        }
+    }
+
+    // Number of tiles energized by a beam entering the tile at the position
+    fn energized(&self, position: (usize, usize), direction: Direction) -> usize {
+        let mut energized = BitSet::with_capacity(self.grid.tiles.len());
+
+        if let Some(splitter) = self.grid.trace(position, direction, &mut energized) {
+            energized |= &self.energized[self.components[self.nodes[splitter]]];
+        }
+
+        energized.len()
     }
 }
 
@@ -163,6 +349,40 @@ enum Tile {
     ReverseMirror,   // \
 }
 
+impl Tile {
+    // Whether a beam moving in the direction is split by the tile
+    fn splits(self, direction: Direction) -> bool {
+        match self {
+            Self::VerticalSplit => matches!(direction, Direction::LEFT | Direction::RIGHT),
+            Self::HorizontalSplit => matches!(direction, Direction::UP | Direction::DOWN),
+            _ => false,
+        }
+    }
+
+    fn symbol(self) -> char {
+        match self {
+            Self::Empty => '.',
+            Self::VerticalSplit => '|',
+            Self::HorizontalSplit => '-',
+            Self::NormalMirror => '/',
+            Self::ReverseMirror => '\\',
+        }
+    }
+}
+
+impl From<u8> for Tile {
+    fn from(value: u8) -> Self {
+        match value {
+            b'.' => Tile::Empty,
+            b'|' => Tile::VerticalSplit,
+            b'-' => Tile::HorizontalSplit,
+            b'/' => Tile::NormalMirror,
+            b'\\' => Tile::ReverseMirror,
+            _ => unreachable!(),
+        }
+    }
+}
+
 #[repr(transparent)]
 #[derive(Copy, Clone, Eq, PartialEq)]
 struct Direction(u8);
@@ -173,10 +393,6 @@ impl Direction {
     const LEFT: Self = Direction(2);
     const RIGHT: Self = Direction(3);
 
-    fn to_flag(self) -> u8 {
-        1 << self.0
-    }
-
     fn normal_mirror(self) -> Self {
         Self(3 - self.0)
     }
@@ -184,4 +400,89 @@ impl Direction {
     fn reverse_mirror(self) -> Self {
         Self((self.0 + 2) % 4)
     }
+
+    fn arrow(self) -> char {
+        ['^', 'v', '<', '>'][self.0 as usize]
+    }
+
+    fn name(self) -> &'static str {
+        ["up", "down", "left", "right"][self.0 as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    const EXAMPLE: &[&str] = &[
+        ".|...\\....",
+        "|.-.\\.....",
+        ".....|-...",
+        "........|.",
+        "..........",
+        ".........\\",
+        "..../.\\\\..",
+        ".-.-/..|..",
+        ".|....-|.\\",
+        "..//.|....",
+    ];
+
+    #[test]
+    fn example() {
+        let graph = BeamGraph::new(Grid::new(EXAMPLE));
+        assert_eq!(graph.energized((0, 0), Direction::RIGHT), 46);
+
+        let best = graph
+            .grid
+            .entry_points()
+            .map(|(position, direction)| graph.energized(position, direction))
+            .max();
+
+        assert_eq!(best, Some(51));
+
+        let report = part_b(EXAMPLE, &Args::parse("report")).unwrap();
+        assert_eq!(part_b(EXAMPLE, &Args::default()).unwrap().to_string(), "51");
+        assert_eq!(report.to_string(), "51 (entering (3, 0) moving down)");
+    }
+
+    #[test]
+    fn matches_following_every_beam() {
+        let mut rng = StdRng::seed_from_u64(16);
+
+        for _ in 0..200 {
+            let width = rng.gen_range(1..20);
+            let height = rng.gen_range(1..20);
+            let density = rng.gen_range(0.0..0.5);
+
+            let rows = (0..height)
+                .map(|_| {
+                    (0..width)
+                        .map(|_| {
+                            if rng.gen_bool(density) {
+                                ['|', '-', '/', '\\'][rng.gen_range(0..4)]
+                            } else {
+                                '.'
+                            }
+                        })
+                        .collect::<String>()
+                })
+                .collect::<Vec<_>>();
+
+            let input = rows.iter().map(String::as_str).collect::<Vec<_>>();
+            let graph = BeamGraph::new(Grid::new(&input));
+
+            for (position, direction) in graph.grid.entry_points() {
+                let beams = graph.grid.fire_beam(position, direction);
+                let expected = beams.iter().filter(|beams| **beams != 0).count();
+
+                assert_eq!(
+                    graph.energized(position, direction),
+                    expected,
+                    "{position:?} in\n{}",
+                    rows.join("\n")
+                );
+            }
+        }
+    }
 }
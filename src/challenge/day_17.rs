@@ -1,132 +1,314 @@
-use std::collections::BinaryHeap;
+use crate::args::Args;
+use std::cmp::Ordering;
 
-// Heat loss is worse around the center of the grid - the highest values (8, 9) only appear there
-// This means that the center should be avoided (by skipping the highest values)
-// This constraint might not work for all inputs
-const MAX_HEAT_LOSS: u8 = 7;
+// Passing `min=<steps>` and `max=<steps>` changes how far the crucible moves before it can turn
+// and before it has to turn, and `render` prints the best path over the grid
+pub fn part_a(input: &[&str], args: &Args) -> anyhow::Result<impl std::fmt::Display> {
+    solve(input, args, 1, 3)
+}
+
+pub fn part_b(input: &[&str], args: &Args) -> anyhow::Result<impl std::fmt::Display> {
+    solve(input, args, 4, 10)
+}
+
+fn solve(input: &[&str], args: &Args, min_steps: usize, max_steps: usize) -> anyhow::Result<usize> {
+    let grid = Grid::parse(input)?;
+    let path = grid.find_best_path(
+        args.get_or("min", min_steps)?,
+        args.get_or("max", max_steps)?,
+    )?;
 
-pub fn part_a(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
-    find_best_path(input, 1, 3)
+    if args.flag("render") {
+        println!("{}", grid.render(&path));
+    }
+
+    Ok(path.heat_loss)
 }
 
-pub fn part_b(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
-    find_best_path(input, 4, 10)
+struct Grid {
+    heat_losses: Vec<u8>,
+    width: usize,
+    height: usize,
 }
 
-// Dijkstra's algorithm
-// Treats all coordinates that can be visited without turning as neighbors
-fn find_best_path(input: &[&str], min_steps: isize, max_steps: isize) -> anyhow::Result<usize> {
-    let width = input[0].len();
-    let height = input.len();
-    let max_x = width - 1;
-    let max_y = height - 1;
-
-    let grid = input
-        .iter()
-        .flat_map(|row| row.bytes())
-        .map(|cost| cost - b'0')
-        .collect::<Vec<_>>();
-
-    let mut queue = BinaryHeap::new();
-    let mut costs = vec![usize::MAX; grid.len() * 4];
-
-    queue.push(Entry::default());
-
-    while let Some(Entry {
-        cost,
-        x,
-        y,
-        direction,
-    }) = queue.pop()
-    {
-        if x == max_x && y == max_y {
-            return Ok(cost);
+impl Grid {
+    fn parse(input: &[&str]) -> anyhow::Result<Self> {
+        let width = input.first().map_or(0, |row| row.len());
+
+        if width == 0 || input.iter().any(|row| row.len() != width) {
+            anyhow::bail!("The grid's rows must all have the same, non-zero, length");
         }
 
-        if costs[x + y * width + direction * grid.len()] < cost {
-            continue;
+        let heat_losses = input
+            .iter()
+            .flat_map(|row| row.bytes())
+            .map(|char| char.wrapping_sub(b'0'))
+            .collect::<Vec<_>>();
+
+        if heat_losses.iter().any(|heat_loss| *heat_loss > 9) {
+            anyhow::bail!("The grid must only contain digits");
         }
 
-        let invalid_direction = if x == 0 && y == 0 {
-            // all directions are valid from the starting position
-            // `something & 1` will only ever return `0` or `1`
-            2
-        } else {
-            direction & 1
-        };
-
-        for (direction, (dx, dy)) in [(1, 0), (0, 1), (-1, 0), (0, -1)].iter().enumerate() {
-            if (direction & 1) == invalid_direction {
-                continue;
-            }
+        Ok(Self {
+            heat_losses,
+            width,
+            height: input.len(),
+        })
+    }
 
-            let mut x = x as isize;
-            let mut y = y as isize;
-            let mut total_heat_loss = 0;
+    // Dijkstra's algorithm using a bucket queue, as the heat loss of a single move is bounded.
+    // The crucible has to turn after every move, so states are positions combined with the axis
+    // of the last move, and all positions which can be reached without turning are neighbors.
+    fn find_best_path(&self, min_steps: usize, max_steps: usize) -> anyhow::Result<Path> {
+        if min_steps == 0 || min_steps > max_steps {
+            anyhow::bail!(
+                "The crucible can't move at least {min_steps} and at most {max_steps} steps, \
+                the minimum must be between 1 and the maximum"
+            );
+        }
 
-            for steps in 1..=max_steps {
-                x += dx;
-                y += dy;
+        let end = self.heat_losses.len() - 1;
 
-                if x < 0 || x >= width as isize || y < 0 || y >= height as isize {
-                    break;
-                }
+        // Moves never lose more than 9 * max_steps heat, so the queued costs always fit in the
+        // buckets without wrapping around onto each other
+        let size = 9 * max_steps + 1;
+        let mut buckets = vec![Vec::new(); size];
+        let mut costs = vec![usize::MAX; self.heat_losses.len() * 2];
+        // the state each state was reached from on its cheapest path
+        let mut previous = vec![usize::MAX; costs.len()];
+        let mut queued = 0;
 
-                let x = x as usize;
-                let y = y as usize;
-                let index = x + y * width;
-                let heat_loss = grid[index];
+        // the crucible can start moving along both axes
+        for state in [0, 1] {
+            costs[state] = 0;
+            buckets[0].push(state);
+            queued += 1;
+        }
 
-                if heat_loss > MAX_HEAT_LOSS {
-                    break;
-                }
+        let mut cost = 0;
+
+        while queued > 0 {
+            let bucket = cost % size;
 
-                total_heat_loss += heat_loss as usize;
+            while let Some(state) = buckets[bucket].pop() {
+                queued -= 1;
 
-                if steps < min_steps {
+                if costs[state] < cost {
                     continue;
                 }
 
-                let cost = cost + total_heat_loss;
-                let id = index + direction * grid.len();
+                let (index, axis) = (state / 2, state % 2);
 
-                if cost >= costs[id] {
-                    // this might not be the cheapest path to this coordinate, but the optimal path might
-                    // require us to go *pass over* it using this path anyway due to turning limitations
-                    continue;
+                if index == end {
+                    return Ok(Path::new(cost, state, &previous));
                 }
 
-                costs[id] = cost;
+                let x = index % self.width;
+                let y = index / self.width;
+
+                // turn onto the other axis, in either direction
+                let offsets = if axis == 0 {
+                    [(0, 1), (0, -1)]
+                } else {
+                    [(1, 0), (-1, 0)]
+                };
+
+                for (dx, dy) in offsets {
+                    let mut x = x as isize;
+                    let mut y = y as isize;
+                    let mut total_heat_loss = 0;
+
+                    for steps in 1..=max_steps {
+                        x += dx;
+                        y += dy;
+
+                        if x < 0 || x >= self.width as isize || y < 0 || y >= self.height as isize {
+                            break;
+                        }
+
+                        let index = x as usize + y as usize * self.width;
+                        total_heat_loss += self.heat_losses[index] as usize;
+
+                        if steps < min_steps {
+                            continue;
+                        }
 
-                queue.push(Entry {
-                    cost,
-                    x,
-                    y,
-                    direction,
-                });
+                        let cost = cost + total_heat_loss;
+                        let next = index * 2 + (1 - axis);
+
+                        // this might not be the cheapest path to this coordinate, but the optimal
+                        // path might require *passing over* it using this path anyway due to the
+                        // turning limitations
+                        if cost >= costs[next] {
+                            continue;
+                        }
+
+                        costs[next] = cost;
+                        previous[next] = state;
+                        buckets[cost % size].push(next);
+                        queued += 1;
+                    }
+                }
             }
+
+            cost += 1;
         }
+
+        anyhow::bail!("Could not find a path");
     }
 
-    anyhow::bail!("Could not find a path");
+    // Draws the path over the grid the same way as the puzzle, followed by the direction and
+    // length of each of its segments (e.g. ">2 v1 >3")
+    fn render(&self, path: &Path) -> String {
+        let mut cells = self
+            .heat_losses
+            .iter()
+            .map(|heat_loss| (b'0' + heat_loss) as char)
+            .collect::<Vec<_>>();
+
+        let mut segments = Vec::with_capacity(path.segments.len());
+
+        for &(start, end) in path.segments.iter() {
+            let (x0, y0) = (start % self.width, start / self.width);
+            let (x1, y1) = (end % self.width, end / self.width);
+
+            let (arrow, stride) = match (x0.cmp(&x1), y0.cmp(&y1)) {
+                (Ordering::Less, _) => ('>', 1),
+                (Ordering::Greater, _) => ('<', 1),
+                (_, Ordering::Less) => ('v', self.width),
+                _ => ('^', self.width),
+            };
+
+            let length = x0.abs_diff(x1) + y0.abs_diff(y1);
+
+            for step in 1..=length {
+                let index = if start < end {
+                    start + step * stride
+                } else {
+                    start - step * stride
+                };
+
+                cells[index] = arrow;
+            }
+
+            segments.push(format!("{arrow}{length}"));
+        }
+
+        let mut output = cells
+            .chunks(self.width)
+            .map(|row| row.iter().collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        output.push_str("\n\n");
+        output.push_str(&segments.join(" "));
+        output
+    }
 }
 
-#[derive(Eq, PartialEq, Default)]
-struct Entry {
-    cost: usize,
-    x: usize,
-    y: usize,
-    direction: usize,
+struct Path {
+    heat_loss: usize,
+    // indexes of the cells at the start and end of each segment, from the top left corner
+    segments: Vec<(usize, usize)>,
 }
 
-impl PartialOrd for Entry {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
+impl Path {
+    // Follows the states back from the end, until reaching one of the starting states
+    fn new(heat_loss: usize, end: usize, previous: &[usize]) -> Self {
+        let mut segments = Vec::new();
+        let mut state = end;
+
+        while previous[state] != usize::MAX {
+            segments.push((previous[state] / 2, state / 2));
+            state = previous[state];
+        }
+
+        segments.reverse();
+
+        Self {
+            heat_loss,
+            segments,
+        }
     }
 }
 
-impl Ord for Entry {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        other.cost.cmp(&self.cost)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &[&str] = &[
+        "2413432311323",
+        "3215453535623",
+        "3255245654254",
+        "3446585845452",
+        "4546657867536",
+        "1438598798454",
+        "4457876987766",
+        "3637877979653",
+        "4654967986887",
+        "4564679986453",
+        "1224686865563",
+        "2546548887735",
+        "4322674655533",
+    ];
+
+    fn heat_loss(input: &[&str], min_steps: usize, max_steps: usize) -> usize {
+        let grid = Grid::parse(input).unwrap();
+        grid.find_best_path(min_steps, max_steps).unwrap().heat_loss
+    }
+
+    #[test]
+    fn examples() {
+        assert_eq!(heat_loss(EXAMPLE, 1, 3), 102);
+        assert_eq!(heat_loss(EXAMPLE, 4, 10), 94);
+
+        let unfortunate = &[
+            "111111111111",
+            "999999999991",
+            "999999999991",
+            "999999999991",
+            "999999999991",
+        ];
+
+        assert_eq!(heat_loss(unfortunate, 4, 10), 71);
+    }
+
+    #[test]
+    fn paths_through_high_heat_loss() {
+        assert_eq!(heat_loss(&["999", "999", "999"], 1, 3), 36);
+        assert_eq!(heat_loss(&["1999", "8999", "8999", "8881"], 1, 3), 41);
+        assert_eq!(
+            heat_loss(&["19999", "99999", "99999", "99999", "99991"], 4, 10),
+            64
+        );
+    }
+
+    #[test]
+    fn render() {
+        let grid = Grid::parse(EXAMPLE).unwrap();
+        let path = grid.find_best_path(1, 3).unwrap();
+
+        let expected = [
+            "2>>34^>>>1323",
+            "32v>>>35v5623",
+            "32552456v>>54",
+            "3446585845v52",
+            "4546657867v>6",
+            "14385987984v4",
+            "44578769877v6",
+            "36378779796v>",
+            "465496798688v",
+            "456467998645v",
+            "12246868655<v",
+            "25465488877v5",
+            "43226746555v>",
+            "",
+            ">2 v1 >3 ^1 >3 v2 >2 v2 >1 v3 >1 v3 <1 v2 >1",
+        ];
+
+        assert_eq!(grid.render(&path), expected.join("\n"));
+
+        let path = grid.find_best_path(4, 10).unwrap();
+        assert!(grid.render(&path).ends_with("\n\n>8 v4 >4 v8"));
     }
 }
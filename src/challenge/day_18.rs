@@ -1,123 +1,471 @@
-use crate::utils::Bytes;
+use crate::{args::Args, utils::Bytes};
+use anyhow::Context;
+use std::fmt::Write;
 
-pub fn part_a(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
-    Ok(measure_area(
-        input,
-        parse_simple_direction,
-        parse_simple_distance,
-    ))
+// Lagoons which are wider or taller than this are rendered as an image
+const MAX_ASCII_SIZE: i64 = 150;
+// Larger images are scaled down to fit
+const MAX_IMAGE_SIZE: i64 = 1000;
+const INTERIOR_COLOR: [u8; 3] = [0x30, 0x30, 0x30];
+
+// Passing `render` prints small lagoons the same way as the puzzle and writes larger ones to a PPM
+// image at `image=<path>` (lagoon.ppm by default), using the colors of the dig plan
+pub fn part_a(input: &[&str], args: &Args) -> anyhow::Result<impl std::fmt::Display> {
+    solve(input, args, parse_simple_step)
 }
 
-pub fn part_b(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
-    Ok(measure_area(
-        input,
-        parse_complex_direction,
-        parse_complex_distance,
-    ))
+pub fn part_b(input: &[&str], args: &Args) -> anyhow::Result<impl std::fmt::Display> {
+    solve(input, args, parse_complex_step)
 }
 
-fn parse_simple_direction(input: &[u8]) -> u8 {
-    input[0]
+fn solve(
+    input: &[&str],
+    args: &Args,
+    parse_step: fn(&[u8]) -> Option<Step>,
+) -> anyhow::Result<i64> {
+    let steps = input
+        .iter()
+        .map(|line| parse_step(line.as_bytes()).with_context(|| format!("Invalid step {line}")))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let lagoon = Lagoon::dig(steps)?;
+
+    if args.flag("render") {
+        lagoon.render(&args.get_or("image", String::from("lagoon.ppm"))?)?;
+    }
+
+    Ok(lagoon.area())
 }
 
-fn parse_complex_direction(input: &[u8]) -> u8 {
-    input[input.len() - 2]
+// parse "R 6 (#70c710)"
+fn parse_simple_step(input: &[u8]) -> Option<Step> {
+    let mut parts = input.split(|char| *char == b' ');
+    let direction = parse_direction(parts.next()?)?;
+    let distance = parts
+        .next()
+        .filter(|value| is_number(value, 10) && is_positive(value))?;
+    let color = parse_color(parts.next()?)?;
+
+    Some(Step {
+        direction,
+        distance: distance.parse_dec(),
+        color,
+    })
 }
 
-fn parse_simple_distance(input: &[u8]) -> i64 {
-    let index = 2 + input[2..].iter().position(|char| *char == b' ').unwrap();
-    input[2..index].parse_dec()
+// parse "R 6 (#70c710)" using the color, where the first 5 digits are the distance and
+// the last digit is the direction
+fn parse_complex_step(input: &[u8]) -> Option<Step> {
+    parse_simple_step(input)?;
+    let hex = &input[input.len() - 7..input.len() - 1];
+
+    if !is_positive(&hex[..5]) {
+        return None;
+    }
+
+    let direction = match hex[5] {
+        b'0' => Direction::Right,
+        b'1' => Direction::Down,
+        b'2' => Direction::Left,
+        b'3' => Direction::Up,
+        _ => return None,
+    };
+
+    Some(Step {
+        direction,
+        distance: hex[..5].parse_hex(),
+        color: parse_color(&input[input.len() - 9..])?,
+    })
 }
 
-fn parse_complex_distance(input: &[u8]) -> i64 {
-    input[input.len() - 7..input.len() - 2].parse_hex()
+fn parse_direction(input: &[u8]) -> Option<Direction> {
+    match input {
+        b"U" => Some(Direction::Up),
+        b"D" => Some(Direction::Down),
+        b"L" => Some(Direction::Left),
+        b"R" => Some(Direction::Right),
+        _ => None,
+    }
 }
 
-fn measure_area(
-    input: &[&str],
-    parse_direction: fn(&[u8]) -> u8,
-    parse_distance: fn(&[u8]) -> i64,
-) -> i64 {
-    let mut x = 0;
-    let mut area = 0;
-
-    let (mut up, mut left) = find_initial_state(input, parse_direction);
-
-    for line in input.iter().map(|line| line.as_bytes()) {
-        let distance = parse_distance(line);
-
-        match parse_direction(line) {
-            b'U' | b'3' => {
-                if up {
-                    area -= x * (distance + 1);
-                } else {
-                    area -= x * distance - (left as i64);
-                }
+// parse "(#70c710)"
+fn parse_color(input: &[u8]) -> Option<[u8; 3]> {
+    let hex = input.strip_prefix(b"(#")?.strip_suffix(b")")?;
+
+    if !is_number(hex, 16) || hex.len() != 6 {
+        return None;
+    }
+
+    Some([
+        hex[0..2].parse_hex(),
+        hex[2..4].parse_hex(),
+        hex[4..6].parse_hex(),
+    ])
+}
+
+// Numbers can't be large enough to overflow
+fn is_number(value: &[u8], radix: u32) -> bool {
+    !value.is_empty()
+        && value.len() < 16
+        && value.iter().all(|char| (*char as char).is_digit(radix))
+}
+
+// Steps have to move at least one cell
+fn is_positive(value: &[u8]) -> bool {
+    value.iter().any(|char| *char != b'0')
+}
+
+struct Lagoon {
+    steps: Vec<Step>,
+    // positions of the trench's corners, where each step starts
+    vertices: Vec<(i64, i64)>,
+}
+
+impl Lagoon {
+    fn dig(steps: Vec<Step>) -> anyhow::Result<Self> {
+        let mut vertices = Vec::with_capacity(steps.len());
+        let mut position = (0, 0);
+
+        for step in steps.iter() {
+            vertices.push(position);
+            let (dx, dy) = step.direction.offset();
+            position = (
+                position.0 + dx * step.distance,
+                position.1 + dy * step.distance,
+            );
+        }
 
-                up = true;
+        if vertices.is_empty() {
+            anyhow::bail!("The dig plan is empty");
+        }
+
+        if position != (0, 0) {
+            anyhow::bail!(
+                "The dig plan doesn't return to its start, it ends at ({}, {})",
+                position.0,
+                position.1
+            );
+        }
+
+        Ok(Self { steps, vertices })
+    }
+
+    fn area(&self) -> i64 {
+        // the trench would be counted multiple times where it overlaps itself
+        if self.overlaps_itself() {
+            return self.fill_area();
+        }
+
+        // The shoelace formula gives the area of the polygon through the centers of the trench's
+        // cells. Pick's theorem (A = I + B / 2 - 1) then gives the number of cells inside of it,
+        // to which the B cells of the trench are added.
+        let double_area = self
+            .edges()
+            .map(|((x0, y0), (x1, y1))| x0 * y1 - x1 * y0)
+            .sum::<i64>()
+            .abs();
+
+        let boundary = self.steps.iter().map(|step| step.distance).sum::<i64>();
+        (double_area + boundary) / 2 + 1
+    }
+
+    fn edges(&self) -> impl Iterator<Item = ((i64, i64), (i64, i64))> + '_ {
+        let next = self.vertices.iter().cycle().skip(1);
+        self.vertices.iter().copied().zip(next.copied())
+    }
+
+    // Whether any of the trench's cells are dug more than once (by edges crossing, touching or
+    // doubling back)
+    fn overlaps_itself(&self) -> bool {
+        let edges = self.edges().collect::<Vec<_>>();
+        let count = edges.len();
+
+        for i in 0..count {
+            for j in i + 1..count {
+                if j == i + 1 || (i == 0 && j == count - 1) {
+                    // adjacent edges share a corner, so they only overlap when turning around
+                    let (a, b) = (self.steps[i].direction, self.steps[j].direction);
+
+                    if a.offset() == b.opposite().offset() {
+                        return true;
+                    }
+                } else if intersects(edges[i], edges[j]) {
+                    return true;
+                }
             }
-            b'D' | b'1' => {
-                if up {
-                    area += (x + 1) * distance + (!left as i64);
-                } else {
-                    area += (x + 1) * (distance + 1);
+        }
+
+        false
+    }
+
+    // Counts the cells of the trench and the cells it encloses by flood filling the outside of
+    // a compressed grid, whose cells cover rectangles of lagoon cells with the same contents.
+    // Unlike Pick's theorem this works for any trench.
+    fn fill_area(&self) -> i64 {
+        let columns = compress(self.vertices.iter().map(|vertex| vertex.0));
+        let rows = compress(self.vertices.iter().map(|vertex| vertex.1));
+        let width = columns.len() - 1;
+        let height = rows.len() - 1;
+
+        let index = |values: &[i64], value: i64| values.binary_search(&value).unwrap();
+        let mut trench = vec![false; width * height];
+
+        for ((x0, y0), (x1, y1)) in self.edges() {
+            for y in index(&rows, y0.min(y1))..index(&rows, y0.max(y1) + 1) {
+                for x in index(&columns, x0.min(x1))..index(&columns, x0.max(x1) + 1) {
+                    trench[y * width + x] = true;
                 }
+            }
+        }
+
+        // the first row and column are always outside of the trench
+        let mut outside = vec![false; width * height];
+        let mut stack = vec![(0usize, 0usize)];
+        outside[0] = true;
 
-                up = false;
+        while let Some((x, y)) = stack.pop() {
+            let neighbors = [
+                (x + 1, y),
+                (x, y + 1),
+                (x.wrapping_sub(1), y),
+                (x, y.wrapping_sub(1)),
+            ];
+
+            for (x, y) in neighbors {
+                if x < width && y < height && !trench[y * width + x] && !outside[y * width + x] {
+                    outside[y * width + x] = true;
+                    stack.push((x, y));
+                }
             }
-            b'L' | b'2' => {
-                if up {
-                    area += x;
+        }
+
+        let mut area = 0;
+
+        for y in 0..height {
+            for x in 0..width {
+                if !outside[y * width + x] {
+                    area += (columns[x + 1] - columns[x]) * (rows[y + 1] - rows[y]);
                 }
+            }
+        }
+
+        area
+    }
+
+    fn render(&self, path: &str) -> anyhow::Result<()> {
+        let min_x = self.vertices.iter().map(|vertex| vertex.0).min().unwrap();
+        let max_x = self.vertices.iter().map(|vertex| vertex.0).max().unwrap();
+        let min_y = self.vertices.iter().map(|vertex| vertex.1).min().unwrap();
+        let max_y = self.vertices.iter().map(|vertex| vertex.1).max().unwrap();
 
-                x -= distance;
+        let width = max_x - min_x + 1;
+        let height = max_y - min_y + 1;
 
-                if !up {
-                    area -= x + 1;
+        if width <= MAX_ASCII_SIZE && height <= MAX_ASCII_SIZE {
+            let mut grid = vec![vec![b'.'; width as usize]; height as usize];
+
+            for ((x0, y0), (x1, y1)) in self.edges() {
+                for y in y0.min(y1)..=y0.max(y1) {
+                    for x in x0.min(x1)..=x0.max(x1) {
+                        grid[(y - min_y) as usize][(x - min_x) as usize] = b'#';
+                    }
                 }
+            }
 
-                left = true;
+            for row in grid {
+                println!("{}", String::from_utf8(row).unwrap());
             }
-            b'R' | b'0' => {
-                if !up {
-                    area -= x + 1;
-                }
 
-                x += distance;
+            return Ok(());
+        }
 
-                if up {
-                    area += x;
-                }
+        // every pixel covers a square of scale x scale cells
+        let scale = (width.max(height) + MAX_IMAGE_SIZE - 1) / MAX_IMAGE_SIZE;
+        let image_width = ((width + scale - 1) / scale) as usize;
+        let image_height = ((height + scale - 1) / scale) as usize;
+        let mut pixels = vec![[0u8; 3]; image_width * image_height];
+
+        // the interior of each row of pixels is determined by the row of cells at its center,
+        // which is inside between every other vertical edge crossing it
+        for py in 0..image_height {
+            let y = min_y + py as i64 * scale + scale / 2;
+
+            let mut crossings = self
+                .edges()
+                .filter(|((x0, y0), (x1, y1))| x0 == x1 && y0.min(y1) <= &y && &y < y0.max(y1))
+                .map(|((x, _), _)| x)
+                .collect::<Vec<_>>();
 
-                left = false;
+            crossings.sort_unstable();
+
+            for pair in crossings.chunks_exact(2) {
+                let start = ((pair[0] - min_x) / scale) as usize;
+                let end = ((pair[1] - min_x) / scale) as usize;
+                pixels[py * image_width + start..=py * image_width + end].fill(INTERIOR_COLOR);
             }
-            _ => unreachable!(),
         }
+
+        for (step, ((x0, y0), (x1, y1))) in self.steps.iter().zip(self.edges()) {
+            let (px0, px1) = ((x0.min(x1) - min_x) / scale, (x0.max(x1) - min_x) / scale);
+            let (py0, py1) = ((y0.min(y1) - min_y) / scale, (y0.max(y1) - min_y) / scale);
+
+            for py in py0..=py1 {
+                for px in px0..=px1 {
+                    pixels[py as usize * image_width + px as usize] = step.color;
+                }
+            }
+        }
+
+        let mut header = String::new();
+        _ = write!(header, "P6\n{image_width} {image_height}\n255\n");
+
+        let mut image = header.into_bytes();
+        image.extend(pixels.into_iter().flatten());
+
+        std::fs::write(path, image)
+            .with_context(|| format!("Failed to write the image to {path}"))?;
+
+        println!("Rendered the lagoon to {path} ({image_width}x{image_height}, 1 pixel = {scale}x{scale} cells)");
+        Ok(())
     }
+}
 
-    area
+// Whether the (inclusive) horizontal or vertical edges share any cells
+fn intersects(a: ((i64, i64), (i64, i64)), b: ((i64, i64), (i64, i64))) -> bool {
+    let ((ax0, ay0), (ax1, ay1)) = a;
+    let ((bx0, by0), (bx1, by1)) = b;
+
+    ax0.min(ax1) <= bx0.max(bx1)
+        && bx0.min(bx1) <= ax0.max(ax1)
+        && ay0.min(ay1) <= by0.max(by1)
+        && by0.min(by1) <= ay0.max(ay1)
 }
 
-fn find_initial_state(input: &[&str], parse_direction: fn(&[u8]) -> u8) -> (bool, bool) {
-    let up = input
-        .iter()
-        .rev()
-        .find_map(|line| match parse_direction(line.as_bytes()) {
-            b'U' | b'3' => Some(true),
-            b'D' | b'1' => Some(false),
-            _ => None,
-        })
-        .unwrap();
-
-    let left = input
-        .iter()
-        .rev()
-        .find_map(|line| match parse_direction(line.as_bytes()) {
-            b'L' | b'2' => Some(true),
-            b'R' | b'0' => Some(false),
-            _ => None,
-        })
-        .unwrap();
-
-    (up, left)
+// The sorted boundaries between the ranges of the compressed grid. Each cell gets its own range
+// and there's an extra range on each side, which is outside of the trench.
+fn compress(values: impl Iterator<Item = i64>) -> Vec<i64> {
+    let mut boundaries = values
+        .flat_map(|value| [value, value + 1])
+        .collect::<Vec<_>>();
+
+    let min = *boundaries.iter().min().unwrap();
+    let max = *boundaries.iter().max().unwrap();
+    boundaries.extend([min - 1, max + 1]);
+    boundaries.sort_unstable();
+    boundaries.dedup();
+    boundaries
+}
+
+struct Step {
+    direction: Direction,
+    distance: i64,
+    color: [u8; 3],
+}
+
+#[derive(Copy, Clone)]
+enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    fn offset(self) -> (i64, i64) {
+        match self {
+            Self::Up => (0, -1),
+            Self::Down => (0, 1),
+            Self::Left => (-1, 0),
+            Self::Right => (1, 0),
+        }
+    }
+
+    fn opposite(self) -> Self {
+        match self {
+            Self::Up => Self::Down,
+            Self::Down => Self::Up,
+            Self::Left => Self::Right,
+            Self::Right => Self::Left,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &[&str] = &[
+        "R 6 (#70c710)",
+        "D 5 (#0dc571)",
+        "L 2 (#5713f0)",
+        "D 2 (#d2c081)",
+        "R 2 (#59c680)",
+        "D 2 (#411b91)",
+        "L 5 (#8ceee2)",
+        "U 2 (#caa173)",
+        "L 1 (#1b58a2)",
+        "U 2 (#caa171)",
+        "R 2 (#7807d2)",
+        "U 3 (#a77fa3)",
+        "L 2 (#015232)",
+        "U 2 (#7a21e3)",
+    ];
+
+    fn dig(input: &[&str], parse_step: fn(&[u8]) -> Option<Step>) -> anyhow::Result<Lagoon> {
+        let steps = input
+            .iter()
+            .map(|line| parse_step(line.as_bytes()).unwrap())
+            .collect();
+
+        Lagoon::dig(steps)
+    }
+
+    #[test]
+    fn example_areas() {
+        let lagoon = dig(EXAMPLE, parse_simple_step).unwrap();
+        assert!(!lagoon.overlaps_itself());
+        assert_eq!(lagoon.area(), 62);
+        assert_eq!(lagoon.fill_area(), 62);
+
+        let lagoon = dig(EXAMPLE, parse_complex_step).unwrap();
+        assert!(!lagoon.overlaps_itself());
+        assert_eq!(lagoon.area(), 952408144115);
+        assert_eq!(lagoon.fill_area(), 952408144115);
+    }
+
+    #[test]
+    fn self_touching_trench() {
+        // #####
+        // ..#.#
+        // ..#.#
+        // ..#.#
+        // ..###
+        let input = [
+            "R 4 (#000000)",
+            "D 4 (#000000)",
+            "L 2 (#000000)",
+            "U 4 (#000000)",
+            "L 2 (#000000)",
+        ];
+
+        let lagoon = dig(&input, parse_simple_step).unwrap();
+        assert!(lagoon.overlaps_itself());
+        assert_eq!(lagoon.area(), 17);
+    }
+
+    #[test]
+    fn trench_which_does_not_close() {
+        let input = [
+            "R 4 (#000000)",
+            "D 4 (#000000)",
+            "L 3 (#000000)",
+            "U 4 (#000000)",
+        ];
+        let error = dig(&input, parse_simple_step).err().unwrap();
+
+        assert_eq!(
+            error.to_string(),
+            "The dig plan doesn't return to its start, it ends at (1, 0)"
+        );
+    }
 }
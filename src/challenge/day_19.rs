@@ -1,143 +1,670 @@
-use crate::utils::{Bytes, IndexMapBuilder};
+use crate::{
+    args::Args,
+    utils::{BitSet, Bytes, IndexMapBuilder, RangeSet},
+};
+use rustc_hash::FxHashMap;
+use std::{borrow::Cow, fmt::Write, ops::Range};
+
+const MIN_VALUE: u64 = 1;
+const MAX_VALUE: u64 = 4000;
 
 pub fn part_a(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
-    let mut parts = input.split(|line| line.is_empty());
-    let workflows = Parser::parse(parts.next().unwrap());
+    let system = System::parse(input)?;
+    let mut sum = 0u64;
+
+    for part in system.parts.iter() {
+        if validate_part(part, &system)? {
+            sum = part
+                .iter()
+                .try_fold(sum, |sum, value| sum.checked_add(*value))
+                .ok_or_else(|| anyhow::anyhow!("The sum of the accepted ratings overflows"))?;
+        }
+    }
 
-    let mut sum = 0;
+    Ok(sum)
+}
 
-    for input in parts.next().unwrap() {
-        let part = parse_part(input);
+// The workflows are compiled into a decision tree, which finds all of the accepted combinations.
+//
+// Passing `min=<value>` and `max=<value>` changes the (inclusive) bounds of the ratings.
+// Passing `report` lists the rules which never match, the workflows which are never reached or
+// always have the same outcome, the groups of workflows with identical decision trees and the
+// cycles between workflows, `rectangles` lists the ranges of
+// accepted ratings and `tree` prints the decision tree.
+pub fn part_b(input: &[&str], args: &Args) -> anyhow::Result<impl std::fmt::Display> {
+    let system = System::parse(input)?;
+    let min = args.get_or("min", MIN_VALUE)?;
+    let max = args.get_or("max", MAX_VALUE)?;
+
+    if min > max {
+        anyhow::bail!("The minimum value {min} is larger than the maximum value {max}");
+    }
 
-        if validate_part(&part, &workflows) {
-            sum += part.iter().map(|value| *value as usize).sum::<usize>();
+    // the ranges exclude their end
+    let Some(end) = max.checked_add(1) else {
+        anyhow::bail!("The maximum value can be at most {}", u64::MAX - 1);
+    };
+
+    let ranges = vec![RangeSet::from(min..end); system.fields.len()];
+    let mut compiler = Compiler::new(&system.workflows);
+    let tree = compiler.compile_workflow(0, ranges.clone());
+
+    if args.flag("tree") {
+        let mut output = String::new();
+        system.describe_tree(&tree, &mut output);
+        print!("{output}");
+    }
+
+    if args.flag("report") {
+        for line in compiler.report(&system, &ranges) {
+            println!("{line}");
         }
     }
 
-    Ok(sum)
-}
+    if args.flag("rectangles") {
+        for ranges in compiler.accepted.iter() {
+            for rectangle in split_rectangles(ranges) {
+                println!("{}", system.describe_rectangle(&rectangle));
+            }
+        }
+    }
+
+    if let Some(cycle) = compiler.cycles.first() {
+        anyhow::bail!(
+            "Some parts loop forever, going through {}",
+            system.describe_cycle(cycle)
+        );
+    }
+
+    let combinations = compiler.accepted.iter().try_fold(0u128, |sum, ranges| {
+        count_combinations(ranges).and_then(|count| sum.checked_add(count))
+    });
 
-pub fn part_b(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
-    let workflows = Parser::parse(input.split(|line| line.is_empty()).next().unwrap());
-    Ok(count_accepted(0, [Range::FULL; 4], 0, &workflows))
+    match combinations {
+        Some(combinations) => Ok(combinations),
+        None => anyhow::bail!("The number of accepted combinations doesn't fit in 128 bits"),
+    }
 }
 
-fn validate_part(part: &[u16; 4], workflows: &[Workflow]) -> bool {
+fn validate_part(part: &[u64], system: &System) -> anyhow::Result<bool> {
     let mut index = 0;
+    let mut visited = vec![0];
 
     loop {
-        let action = workflows[index]
+        let workflow = &system.workflows[index];
+        let action = workflow
             .rules
             .iter()
-            .find(|rule| {
-                rule.value != 0
-                    && match rule.comparison {
-                        Comparison::Less => part[rule.field as usize] < rule.value,
-                        Comparison::Greater => part[rule.field as usize] > rule.value,
-                    }
-            })
+            .find(|rule| rule.comparison.matches(part[rule.field], rule.value))
             .map(|rule| rule.action)
-            .unwrap_or(workflows[index].default);
+            .unwrap_or(workflow.default);
 
         match action {
             Action::Accept => {
-                break true;
+                break Ok(true);
             }
             Action::Reject => {
-                break false;
+                break Ok(false);
+            }
+            Action::Workflow(next) if visited.contains(&next) => {
+                let start = visited.iter().position(|id| *id == next).unwrap();
+                visited.push(next);
+
+                anyhow::bail!(
+                    "Part {} loops forever, going through {}",
+                    system.describe_part(part),
+                    system.describe_cycle(&visited[start..])
+                );
             }
             Action::Workflow(next) => {
-                index = next as usize;
+                index = next;
+                visited.push(next);
             }
         }
     }
 }
 
-fn count_accepted(
-    index: usize,
-    mut ranges: [Range; 4],
-    mut accepted: u64,
-    workflows: &[Workflow],
-) -> u64 {
-    for rule in workflows[index]
-        .rules
-        .iter()
-        .take_while(|rule| rule.value != 0)
-    {
-        let field = rule.field as usize;
-        let mut matched_range = ranges[field].clone();
-
-        match rule.comparison {
-            Comparison::Less => matched_range.end = matched_range.end.min(rule.value - 1),
-            Comparison::Greater => matched_range.start = matched_range.start.max(rule.value + 1),
-        };
+// Returns `None` when the number of combinations overflows
+fn count_combinations(ranges: &[RangeSet]) -> Option<u128> {
+    ranges.iter().try_fold(1u128, |product, range| {
+        product.checked_mul(range.len() as u128)
+    })
+}
 
-        if matched_range.start > matched_range.end {
-            // no match
-            continue;
-        } else if matched_range == ranges[field] {
-            // full match
-            return match rule.action {
-                Action::Accept => accepted + count_combinations(&ranges),
-                Action::Reject => accepted,
-                Action::Workflow(next) => {
-                    count_accepted(next as usize, ranges, accepted, workflows)
+// Splits the combinations into hyper-rectangles, as the ranges of a field might have gaps
+fn split_rectangles(ranges: &[RangeSet]) -> Vec<Vec<Range<u64>>> {
+    let mut rectangles = vec![Vec::with_capacity(ranges.len())];
+
+    for ranges in ranges {
+        rectangles = rectangles
+            .into_iter()
+            .flat_map(|rectangle| {
+                ranges.ranges().map(move |range| {
+                    let mut rectangle = rectangle.clone();
+                    rectangle.push(range);
+                    rectangle
+                })
+            })
+            .collect();
+    }
+
+    rectangles
+}
+
+// A decision tree equivalent to the workflows, without any of the rules which never match or
+// always match for the combinations that reach them.
+// Long rule chains make deep trees, so nothing walks the tree recursively (including dropping it).
+enum Decision {
+    Accept,
+    Reject,
+    // Parts which reach the workflow again, looping forever
+    Loop(usize),
+    Rule {
+        field: usize,
+        comparison: Comparison,
+        value: u64,
+        matched: Box<Decision>,
+        remaining: Box<Decision>,
+    },
+}
+
+impl Decision {
+    // The nodes of the tree in pre-order, which identify the tree as every rule has two children
+    fn nodes(&self) -> Vec<Node> {
+        let mut nodes = Vec::new();
+        let mut stack = vec![self];
+
+        while let Some(decision) = stack.pop() {
+            nodes.push(match decision {
+                Self::Accept => Node::Accept,
+                Self::Reject => Node::Reject,
+                Self::Loop(id) => Node::Loop(*id),
+                Self::Rule {
+                    field,
+                    comparison,
+                    value,
+                    matched,
+                    remaining,
+                } => {
+                    stack.extend([&**remaining, &**matched]);
+                    Node::Rule(*field, *comparison, *value)
                 }
-            };
+            });
         }
 
-        // partial match
-        let mut remaining_range = ranges[field].clone();
+        nodes
+    }
+}
+
+impl Drop for Decision {
+    fn drop(&mut self) {
+        let Self::Rule {
+            matched, remaining, ..
+        } = self
+        else {
+            return;
+        };
 
-        if matched_range.start == remaining_range.start {
-            remaining_range.start = matched_range.end + 1;
-        } else {
-            remaining_range.end = matched_range.start - 1;
+        // the children are moved out before being dropped, so every drop only sees leaves
+        let mut stack = vec![
+            std::mem::replace(&mut **matched, Self::Reject),
+            std::mem::replace(&mut **remaining, Self::Reject),
+        ];
+
+        while let Some(mut decision) = stack.pop() {
+            if let Self::Rule {
+                matched, remaining, ..
+            } = &mut decision
+            {
+                stack.push(std::mem::replace(&mut **matched, Self::Reject));
+                stack.push(std::mem::replace(&mut **remaining, Self::Reject));
+            }
         }
+    }
+}
+
+#[derive(PartialEq, Eq, Hash)]
+enum Node {
+    Accept,
+    Reject,
+    Loop(usize),
+    Rule(usize, Comparison, u64),
+}
+
+// Builds the decision of a chain of rules from its tail, where the remaining combinations of each
+// rule continue to the next one
+fn chain_rules(splits: Vec<(Rule, Decision)>, tail: Decision) -> Decision {
+    let mut decision = tail;
+
+    for (rule, matched) in splits.into_iter().rev() {
+        // the rule doesn't matter when both of its outcomes are the same
+        decision = match (matched, decision) {
+            (Decision::Accept, Decision::Accept) => Decision::Accept,
+            (Decision::Reject, Decision::Reject) => Decision::Reject,
+            (matched, remaining) => Decision::Rule {
+                field: rule.field,
+                comparison: rule.comparison,
+                value: rule.value,
+                matched: Box::new(matched),
+                remaining: Box::new(remaining),
+            },
+        };
+    }
 
-        ranges[field] = matched_range;
+    decision
+}
 
-        match rule.action {
-            Action::Accept => accepted += count_combinations(&ranges),
-            Action::Reject => {}
-            Action::Workflow(next) => {
-                accepted += count_accepted(next as usize, ranges.clone(), 0, workflows)
+// Compiles the workflows, by following the ranges of combinations that reach every rule
+struct Compiler<'a> {
+    workflows: &'a [Workflow],
+    // which rules of each workflow matched some of the combinations
+    matched_rules: Vec<Vec<bool>>,
+    // workflows whose default action was used by some of the combinations
+    used_defaults: BitSet,
+    reached: BitSet,
+    // workflows which accepted and rejected some of the combinations
+    accepts: BitSet,
+    rejects: BitSet,
+    // workflows currently being compiled
+    stack: Vec<usize>,
+    cycles: Vec<Vec<usize>>,
+    accepted: Vec<Vec<RangeSet>>,
+    rejected: usize,
+}
+
+impl<'a> Compiler<'a> {
+    fn new(workflows: &'a [Workflow]) -> Self {
+        Self {
+            workflows,
+            matched_rules: workflows
+                .iter()
+                .map(|workflow| vec![false; workflow.rules.len()])
+                .collect(),
+            used_defaults: BitSet::with_capacity(workflows.len()),
+            reached: BitSet::with_capacity(workflows.len()),
+            accepts: BitSet::with_capacity(workflows.len()),
+            rejects: BitSet::with_capacity(workflows.len()),
+            stack: Vec::new(),
+            cycles: Vec::new(),
+            accepted: Vec::new(),
+            rejected: 0,
+        }
+    }
+
+    fn compile_workflow(&mut self, id: usize, ranges: Vec<RangeSet>) -> Decision {
+        // the ranges never grow, so the same combinations would reach this workflow again
+        if let Some(start) = self.stack.iter().position(|&other| other == id) {
+            let mut cycle = self.stack[start..].to_vec();
+            cycle.push(id);
+            self.cycles.push(cycle);
+            return Decision::Loop(id);
+        }
+
+        let accepted = self.accepted.len();
+        let rejected = self.rejected;
+
+        self.reached.insert(id);
+        self.stack.push(id);
+        let decision = self.compile_rules(id, ranges);
+        self.stack.pop();
+
+        if self.accepted.len() > accepted {
+            self.accepts.insert(id);
+        }
+
+        if self.rejected > rejected {
+            self.rejects.insert(id);
+        }
+
+        decision
+    }
+
+    fn compile_rules(&mut self, id: usize, mut ranges: Vec<RangeSet>) -> Decision {
+        let workflow = &self.workflows[id];
+        // rules which split the combinations, along with the decision for the matched ones
+        let mut splits = Vec::new();
+
+        for (index, rule) in workflow.rules.iter().enumerate() {
+            let (matched, remaining) = rule.comparison.split(&ranges[rule.field], rule.value);
+
+            if matched.is_empty() {
+                continue;
+            }
+
+            self.matched_rules[id][index] = true;
+
+            if remaining.is_empty() {
+                let decision = self.compile_action(rule.action, ranges);
+                return chain_rules(splits, decision);
             }
+
+            let mut matched_ranges = ranges.clone();
+            matched_ranges[rule.field] = matched;
+            ranges[rule.field] = remaining;
+
+            splits.push((*rule, self.compile_action(rule.action, matched_ranges)));
         }
 
-        ranges[field] = remaining_range;
+        self.used_defaults.insert(id);
+        let decision = self.compile_action(workflow.default, ranges);
+        chain_rules(splits, decision)
+    }
+
+    fn compile_action(&mut self, action: Action, ranges: Vec<RangeSet>) -> Decision {
+        match action {
+            Action::Accept => {
+                self.accepted.push(ranges);
+                Decision::Accept
+            }
+            Action::Reject => {
+                self.rejected += 1;
+                Decision::Reject
+            }
+            Action::Workflow(next) => self.compile_workflow(next, ranges),
+        }
     }
 
-    match workflows[index].default {
-        Action::Accept => accepted + count_combinations(&ranges),
-        Action::Reject => accepted,
-        Action::Workflow(index) => count_accepted(index as usize, ranges, accepted, workflows),
+    // The ranges are the combinations of ratings the workflows were compiled for
+    fn report(&self, system: &System, ranges: &[RangeSet]) -> Vec<String> {
+        let mut lines = Vec::new();
+
+        for (id, workflow) in self.workflows.iter().enumerate() {
+            let name = system.workflow_name(id);
+
+            if !self.reached.contains(id) {
+                lines.push(format!("Workflow {name} is never reached"));
+                continue;
+            }
+
+            for (index, rule) in workflow.rules.iter().enumerate() {
+                if !self.matched_rules[id][index] {
+                    lines.push(format!(
+                        "Rule {} of workflow {name} ({}) never matches",
+                        index + 1,
+                        system.describe_rule(rule),
+                    ));
+                }
+            }
+
+            if !self.used_defaults.contains(id) {
+                lines.push(format!(
+                    "Default of workflow {name} ({}) is never used",
+                    system.describe_action(workflow.default),
+                ));
+            }
+
+            match (self.accepts.contains(id), self.rejects.contains(id)) {
+                (true, false) => lines.push(format!("Workflow {name} always accepts")),
+                (false, true) => lines.push(format!("Workflow {name} always rejects")),
+                _ => {}
+            }
+        }
+
+        for group in self.equivalent_workflows(ranges) {
+            let names = group
+                .iter()
+                .map(|&id| system.workflow_name(id))
+                .collect::<Vec<_>>();
+
+            lines.push(format!(
+                "Workflows {} have identical decision trees",
+                names.join(", ")
+            ));
+        }
+
+        for cycle in self.cycles.iter() {
+            lines.push(format!(
+                "Workflows loop forever: {}",
+                system.describe_cycle(cycle)
+            ));
+        }
+
+        lines
+    }
+
+    // Groups of workflows which compile to the same decision tree on their own. Workflows making
+    // the same decisions using different rules (e.g. m>5:A,R and m<6:R,A) aren't grouped.
+    fn equivalent_workflows(&self, ranges: &[RangeSet]) -> Vec<Vec<usize>> {
+        let mut groups = Vec::<Vec<usize>>::new();
+        let mut indexes = FxHashMap::<Vec<Node>, usize>::default();
+
+        for id in 0..self.workflows.len() {
+            let decision = Compiler::new(self.workflows).compile_workflow(id, ranges.to_vec());
+
+            let index = *indexes.entry(decision.nodes()).or_insert_with(|| {
+                groups.push(Vec::new());
+                groups.len() - 1
+            });
+
+            groups[index].push(id);
+        }
+
+        groups.retain(|group| group.len() > 1);
+        groups
     }
 }
 
-fn count_combinations(ranges: &[Range; 4]) -> u64 {
-    ranges.iter().map(Range::count).product::<u64>()
+struct System<'a> {
+    // the first workflow is always "in"
+    workflows: Vec<Workflow>,
+    names: Vec<&'a [u8]>,
+    // names of the rated fields, in the order of the part's values
+    fields: Vec<&'a [u8]>,
+    parts: Vec<Vec<u64>>,
+}
+
+impl<'a> System<'a> {
+    fn parse(input: &'a [&'a str]) -> anyhow::Result<Self> {
+        let mut sections = input.split(|line| line.is_empty());
+        let workflow_lines = sections.next().unwrap_or_default();
+        let part_lines = sections.next().unwrap_or_default();
+
+        let mut parser = Parser {
+            workflows: IndexMapBuilder::with_capacity(workflow_lines.len()),
+            fields: IndexMapBuilder::default(),
+            defined: BitSet::with_capacity(workflow_lines.len()),
+        };
+
+        parser.workflows.reserve(b"in");
+
+        for input in workflow_lines {
+            parser.parse_workflow(input)?;
+        }
+
+        let parts = part_lines
+            .iter()
+            .map(|input| parser.parse_part(input))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let (workflows, names) = parser.workflows.build_with_keys();
+
+        if let Some(id) = (0..names.len()).find(|&id| !parser.defined.contains(id)) {
+            anyhow::bail!(
+                "Workflow {} is never defined",
+                String::from_utf8_lossy(names[id])
+            );
+        }
+
+        let fields = parser.fields.build_with_keys().1;
+        let mut complete_parts = Vec::with_capacity(parts.len());
+
+        // every part has to rate every field, even ones which were only discovered by later parts
+        for (input, part) in part_lines.iter().zip(parts) {
+            let part = (0..fields.len())
+                .map(|field| match part.get(field) {
+                    Some(Some(value)) => Ok(*value),
+                    _ => Err(anyhow::anyhow!(
+                        "Part {input} is missing a rating for {}",
+                        String::from_utf8_lossy(fields[field])
+                    )),
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+
+            complete_parts.push(part);
+        }
+
+        Ok(Self {
+            workflows,
+            names,
+            fields,
+            parts: complete_parts,
+        })
+    }
+
+    fn workflow_name(&self, id: usize) -> Cow<'_, str> {
+        String::from_utf8_lossy(self.names[id])
+    }
+
+    fn describe_action(&self, action: Action) -> Cow<'_, str> {
+        match action {
+            Action::Accept => "A".into(),
+            Action::Reject => "R".into(),
+            Action::Workflow(id) => self.workflow_name(id),
+        }
+    }
+
+    // Formats the rule the same way as the input, e.g. "a<2006:qkq"
+    fn describe_rule(&self, rule: &Rule) -> String {
+        format!(
+            "{}{}{}:{}",
+            String::from_utf8_lossy(self.fields[rule.field]),
+            rule.comparison.symbol(),
+            rule.value,
+            self.describe_action(rule.action),
+        )
+    }
+
+    fn describe_cycle(&self, cycle: &[usize]) -> String {
+        let names = cycle
+            .iter()
+            .map(|&id| self.workflow_name(id))
+            .collect::<Vec<_>>();
+
+        names.join(" -> ")
+    }
+
+    // Formats the part the same way as the input, e.g. "{x=787,m=2655,a=1222,s=2876}"
+    fn describe_part(&self, part: &[u64]) -> String {
+        let ratings = part
+            .iter()
+            .zip(self.fields.iter())
+            .map(|(value, name)| format!("{}={value}", String::from_utf8_lossy(name)))
+            .collect::<Vec<_>>();
+
+        format!("{{{}}}", ratings.join(","))
+    }
+
+    // Formats the inclusive range of each field, e.g. "x=1..=1415 m=1..=4000"
+    fn describe_rectangle(&self, rectangle: &[Range<u64>]) -> String {
+        let fields = rectangle
+            .iter()
+            .zip(self.fields.iter())
+            .map(|(range, name)| {
+                let name = String::from_utf8_lossy(name);
+                format!("{name}={}..={}", range.start, range.end - 1)
+            })
+            .collect::<Vec<_>>();
+
+        fields.join(" ")
+    }
+
+    fn describe_tree(&self, decision: &Decision, output: &mut String) {
+        // `None` stands for the else between the branches of a rule
+        let mut stack = vec![(Some(decision), 0)];
+
+        while let Some((decision, depth)) = stack.pop() {
+            let indent = "  ".repeat(depth);
+
+            match decision {
+                None => _ = writeln!(output, "{indent}else"),
+                Some(Decision::Accept) => _ = writeln!(output, "{indent}A"),
+                Some(Decision::Reject) => _ = writeln!(output, "{indent}R"),
+                Some(Decision::Loop(id)) => {
+                    _ = writeln!(output, "{indent}loop to {}", self.workflow_name(*id))
+                }
+                Some(Decision::Rule {
+                    field,
+                    comparison,
+                    value,
+                    matched,
+                    remaining,
+                }) => {
+                    let field = String::from_utf8_lossy(self.fields[*field]);
+                    _ = writeln!(output, "{indent}if {field}{}{value}", comparison.symbol());
+                    stack.extend([
+                        (Some(&**remaining), depth + 1),
+                        (None, depth),
+                        (Some(&**matched), depth + 1),
+                    ]);
+                }
+            }
+        }
+    }
 }
 
 #[derive(Clone, Default)]
 struct Workflow {
-    rules: [Rule; 3],
+    rules: Vec<Rule>,
     default: Action,
 }
 
-#[derive(Copy, Clone, Default)]
+#[derive(Copy, Clone)]
 struct Rule {
-    field: u8,
+    field: usize,
     comparison: Comparison,
-    value: u16,
+    value: u64,
     action: Action,
 }
 
-#[derive(Copy, Clone, Default)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
 enum Comparison {
-    #[default]
     Less,
+    LessOrEqual,
     Greater,
+    GreaterOrEqual,
+    Equal,
+}
+
+impl Comparison {
+    fn symbol(self) -> &'static str {
+        match self {
+            Self::Less => "<",
+            Self::LessOrEqual => "<=",
+            Self::Greater => ">",
+            Self::GreaterOrEqual => ">=",
+            Self::Equal => "==",
+        }
+    }
+
+    fn matches(self, value: u64, target: u64) -> bool {
+        match self {
+            Self::Less => value < target,
+            Self::LessOrEqual => value <= target,
+            Self::Greater => value > target,
+            Self::GreaterOrEqual => value >= target,
+            Self::Equal => value == target,
+        }
+    }
+
+    // Splits the values into the ones which match the comparison and the ones which don't
+    fn split(self, values: &RangeSet, target: u64) -> (RangeSet, RangeSet) {
+        match self {
+            Self::Less => values.split_at(target),
+            Self::LessOrEqual => values.split_at(target + 1),
+            Self::Greater => {
+                let (below, above) = values.split_at(target + 1);
+                (above, below)
+            }
+            Self::GreaterOrEqual => {
+                let (below, above) = values.split_at(target);
+                (above, below)
+            }
+            Self::Equal => {
+                let target = RangeSet::from(target..target + 1);
+                (values.intersection(&target), values.difference(&target))
+            }
+        }
+    }
 }
 
 #[derive(Copy, Clone, Default)]
@@ -145,112 +672,170 @@ enum Action {
     #[default]
     Accept,
     Reject,
-    Workflow(u16),
+    Workflow(usize),
 }
 
-#[derive(Clone, Eq, PartialEq)]
-struct Range {
-    start: u16,
-    end: u16,
+struct Parser<'a> {
+    workflows: IndexMapBuilder<&'a [u8], Workflow>,
+    fields: IndexMapBuilder<&'a [u8], ()>,
+    // ids of the workflows which were defined, the rest was only referenced so far
+    defined: BitSet,
 }
 
-impl Range {
-    const FULL: Self = Self {
-        start: 1,
-        end: 4000,
-    };
+impl<'a> Parser<'a> {
+    // parse "px{a<2006:qkq,m>2090:A,rfg}"
+    fn parse_workflow(&mut self, input: &'a str) -> anyhow::Result<()> {
+        let invalid = || anyhow::anyhow!("Invalid workflow {input}");
+        let bytes = input.as_bytes();
+
+        let start = bytes.iter().position(|char| *char == b'{');
+        let start = start
+            .filter(|_| bytes.ends_with(b"}"))
+            .ok_or_else(invalid)?;
+        let mut rules = bytes[start + 1..bytes.len() - 1].split(|char| *char == b',');
+        let default = rules.next_back().filter(|default| !default.is_empty());
+
+        let id = self.workflows.find_index(&bytes[..start]);
+
+        if !self.defined.insert(id) {
+            anyhow::bail!("Workflow {} is defined twice", &input[..start]);
+        }
+
+        let rules = rules
+            .map(|rule| self.parse_rule(rule).ok_or_else(invalid))
+            .collect::<anyhow::Result<_>>()?;
 
-    fn count(&self) -> u64 {
-        (self.end - self.start) as u64 + 1
+        let default = self.parse_action(default.ok_or_else(invalid)?);
+        self.workflows[id] = Workflow { rules, default };
+        Ok(())
     }
-}
 
-struct Parser<'a>(IndexMapBuilder<&'a [u8], Workflow>);
+    // parse "a<2006:qkq"
+    fn parse_rule(&mut self, input: &'a [u8]) -> Option<Rule> {
+        let operator = input.iter().position(|char| b"<>=".contains(char))?;
+        let colon = input.iter().rposition(|char| *char == b':')?;
+        let value = input[operator..colon].iter().position(u8::is_ascii_digit)? + operator;
+
+        let comparison = match &input[operator..value] {
+            b"<" => Comparison::Less,
+            b"<=" => Comparison::LessOrEqual,
+            b">" => Comparison::Greater,
+            b">=" => Comparison::GreaterOrEqual,
+            b"==" => Comparison::Equal,
+            _ => return None,
+        };
 
-impl<'a> Parser<'a> {
-    fn parse(input: &'a [&'a str]) -> Vec<Workflow> {
-        let mut parser = Self(IndexMapBuilder::with_capacity(input.len()));
-        parser.0.reserve(b"in");
+        Some(Rule {
+            field: self.parse_field(&input[..operator])?,
+            comparison,
+            value: parse_value(&input[value..colon])?,
+            action: self.parse_action(&input[colon + 1..]),
+        })
+    }
 
-        for input in input {
-            parser.parse_workflow(input.as_bytes());
+    fn parse_action(&mut self, value: &'a [u8]) -> Action {
+        match value {
+            [b'A'] => Action::Accept,
+            [b'R'] => Action::Reject,
+            _ => Action::Workflow(self.workflows.find_index(value)),
         }
-
-        parser.0.build()
     }
 
-    fn parse_workflow(&mut self, input: &'a [u8]) {
-        let start = input.iter().position(|char| *char == b'{').unwrap();
-        let end = input.iter().rposition(|char| *char == b',').unwrap();
-
-        let id = self.0.find_index(&input[..start]);
-        let rules = self.parse_rules(&input[start + 1..end]);
-        let default = self.parse_action(&input[end + 1..input.len() - 1]);
-
-        self.0[id] = Workflow { rules, default };
+    fn parse_field(&mut self, name: &'a [u8]) -> Option<usize> {
+        (!name.is_empty()).then(|| self.fields.find_index(name))
     }
 
-    fn parse_rules(&mut self, input: &'a [u8]) -> [Rule; 3] {
-        let mut rules = [Rule::default(); 3];
+    // parse "{x=787,m=2655,a=1222,s=2876}" into the values of each field (if rated)
+    fn parse_part(&mut self, input: &'a str) -> anyhow::Result<Vec<Option<u64>>> {
+        let invalid = || anyhow::anyhow!("Invalid part {input}");
+        let bytes = input.as_bytes();
 
-        for (index, input) in input.split(|char| *char == b',').enumerate() {
-            let i = input.iter().rposition(|char| *char == b':').unwrap();
+        if !bytes.starts_with(b"{") || !bytes.ends_with(b"}") {
+            return Err(invalid());
+        }
 
-            let field = parse_field_index(input[0]);
-            let comparison = parse_comparison(input[1]);
-            let value = input[2..i].parse_dec();
-            let action = self.parse_action(&input[i + 1..]);
+        let mut part = Vec::new();
 
-            rules[index] = Rule {
-                field,
-                comparison,
-                value,
-                action,
-            };
-        }
+        for rating in bytes[1..bytes.len() - 1].split(|char| *char == b',') {
+            let i = rating
+                .iter()
+                .position(|char| *char == b'=')
+                .ok_or_else(invalid)?;
+            let field = self.parse_field(&rating[..i]).ok_or_else(invalid)?;
+            let value = parse_value(&rating[i + 1..]).ok_or_else(invalid)?;
 
-        rules
-    }
+            if part.len() <= field {
+                part.resize(field + 1, None);
+            }
 
-    fn parse_action(&mut self, value: &'a [u8]) -> Action {
-        match value {
-            [b'A'] => Action::Accept,
-            [b'R'] => Action::Reject,
-            _ => Action::Workflow(self.0.find_index(value) as u16),
+            part[field] = Some(value);
         }
+
+        Ok(part)
     }
 }
 
-// parse "{x=787,m=2655,a=1222,s=2876}" into an array with 4 numbers
-fn parse_part(input: &str) -> [u16; 4] {
-    let mut iter = input
-        .as_bytes()
-        .split(|char| !char.is_ascii_digit())
-        .filter(|value| !value.is_empty())
-        .map(|value| value.parse_dec());
-
-    [
-        iter.next().unwrap(),
-        iter.next().unwrap(),
-        iter.next().unwrap(),
-        iter.next().unwrap(),
-    ]
+fn parse_value(value: &[u8]) -> Option<u64> {
+    let valid = !value.is_empty() && value.len() < 20 && value.iter().all(u8::is_ascii_digit);
+    valid.then(|| value.parse_dec())
 }
 
-fn parse_field_index(value: u8) -> u8 {
-    match value {
-        b'x' => 0,
-        b'm' => 1,
-        b'a' => 2,
-        b's' => 3,
-        _ => unreachable!(),
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overflowing_combinations_are_errors() {
+        let input = ["in{a<2:R,b<2:R,c<2:R,A}", "", "{a=1,b=1,c=1}"];
+
+        assert!(part_b(&input, &Args::default()).is_ok());
+        assert!(part_b(&input, &Args::parse("max=18446744073709551615")).is_err());
+        assert!(part_b(&input, &Args::parse("max=18446744073709551614")).is_err());
+        assert!(part_b(&input, &Args::parse("max=1000000000000")).is_ok());
+    }
+
+    #[test]
+    fn groups_equivalent_workflows() {
+        let input = [
+            "in{x<10:a,x<20:b,c}",
+            "a{m>5:A,R}",
+            "b{m>5:A,R}",
+            "c{m>5:A,m<3:R,R}",
+            "d{m<6:R,A}",
+        ];
+
+        let system = System::parse(&input).unwrap();
+        let ranges = vec![RangeSet::from(1..4001); system.fields.len()];
+        let mut compiler = Compiler::new(&system.workflows);
+        compiler.compile_workflow(0, ranges.clone());
+
+        // d compares differently, so its tree differs even though it has the same outcomes
+        assert_eq!(compiler.equivalent_workflows(&ranges), [vec![1, 2, 3]]);
     }
-}
 
-fn parse_comparison(value: u8) -> Comparison {
-    match value {
-        b'<' => Comparison::Less,
-        b'>' => Comparison::Greater,
-        _ => unreachable!(),
+    #[test]
+    fn compiles_long_rule_chains() {
+        let rules = (0..20000)
+            .map(|value| format!("x=={value}:R"))
+            .collect::<Vec<_>>();
+
+        let workflow = format!("in{{{},A}}", rules.join(","));
+        let input = [workflow.as_str(), "", "{x=20000}"];
+        let system = System::parse(&input).unwrap();
+        let ranges = vec![RangeSet::from(0..40000)];
+        let mut compiler = Compiler::new(&system.workflows);
+        let tree = compiler.compile_workflow(0, ranges.clone());
+
+        let mut output = String::new();
+        system.describe_tree(&tree, &mut output);
+        assert_eq!(output.lines().count(), 20000 * 3 + 1);
+        assert_eq!(output.lines().last().map(str::trim), Some("A"));
+
+        assert_eq!(compiler.accepted.len(), 1);
+        assert_eq!(count_combinations(&compiler.accepted[0]), Some(20000));
+        assert_eq!(
+            compiler.equivalent_workflows(&ranges),
+            Vec::<Vec<usize>>::new()
+        );
     }
 }
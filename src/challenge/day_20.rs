@@ -1,162 +1,306 @@
-use crate::utils::IndexMapBuilder;
+use crate::{
+    args::Args,
+    utils::{graph, BitSet, IndexMapBuilder},
+};
+use anyhow::Context;
 use gcd::Gcd;
-use std::{collections::VecDeque, ops::BitOrAssign};
+use std::{collections::VecDeque, fmt::Write};
 
-const RX_INDEX: usize = 1;
+const MAX_PRESSES: u64 = 1000000;
 
-pub fn part_a(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
-    let mut queue = VecDeque::new();
-    let mut connections = parse(input);
+// Passing `trace=<presses>` prints every pulse sent during the first presses
+pub fn part_a(input: &[&str], args: &Args) -> anyhow::Result<impl std::fmt::Display> {
+    let mut network = Network::parse(input);
+    let trace = args.get_or("trace", 0)?;
 
     let mut low_count = 0;
     let mut high_count = 0;
 
-    for _ in 0..1000 {
-        let (l, h, _) = simulate(&mut connections, &mut queue, 0);
-        low_count += l;
-        high_count += h;
+    for press in 0..1000 {
+        network.press(|network, pulse| {
+            if pulse.state {
+                high_count += 1;
+            } else {
+                low_count += 1;
+            }
+
+            if press < trace {
+                println!("{}", network.describe(pulse));
+            }
+        });
     }
 
     Ok(low_count * high_count)
 }
 
-pub fn part_b(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
-    let mut queue = VecDeque::new();
-    let mut connections = parse(input);
+// Passing `target=<module>` counts the presses until a different module receives a low pulse
+// and `dot=<path>` exports the network as a Graphviz graph before solving it
+pub fn part_b(input: &[&str], args: &Args) -> anyhow::Result<impl std::fmt::Display> {
+    let mut network = Network::parse(input);
+    let name = args.get_or("target", String::from("rx"))?;
 
-    let last_conjunction = connections
-        .iter()
-        .position(|connection| connection.children.has(RX_INDEX))
-        .unwrap();
+    if let Some(path) = args.get::<String>("dot")? {
+        std::fs::write(&path, network.to_dot())
+            .with_context(|| format!("Failed to write the graph to {path}"))?;
+    }
 
-    let required = match connections[last_conjunction].module {
-        Module::Conjunction { required, .. } => required,
-        _ => unreachable!(),
+    let target = match network.find(name.as_bytes()) {
+        Some(target) => target,
+        None => anyhow::bail!("Module {name} does not exist"),
     };
 
-    let mut seen = BitSet::default();
-
     // Visualizing the problem shows that the modules form 4 "loops".
     // Exactly one of the modules of a loop receives pulses from the broadcaster and exactly one of
     // the modules emits a pulse to the conjunction module before rx.
     // Because of how the loops are structured, they emit a high pulse on exact intervals.
 
-    // This solution assumes that rx receives pulses from a single conjunction module and that
-    // its input modules emit high pulses on exact interval. It doesn't assume 4 loops as I could
+    // When the target receives pulses from a single conjunction module, this solution assumes that
+    // its input modules emit high pulses on exact intervals. It doesn't assume 4 loops as I could
     // not confirm that when implementing the solution.
+    // Otherwise (and until the intervals are known), it simulates button presses until the target
+    // receives a low pulse.
 
-    let mut step = 0u64;
-    let mut solution = 1u64;
+    let conjunction = match network.inputs(target)[..] {
+        [input] if network.is_conjunction(input) => Some(input),
+        _ => None,
+    };
 
-    loop {
-        step += 1;
+    let inputs = match conjunction {
+        Some(conjunction) => network.inputs(conjunction),
+        None => Vec::new(),
+    };
 
-        let (_, _, sources) = simulate(&mut connections, &mut queue, last_conjunction);
+    let mut highs = vec![Vec::new(); inputs.len()];
 
-        if sources.is_empty() {
-            continue;
-        }
+    for press in 1..=MAX_PRESSES {
+        let mut reached = false;
 
-        seen |= sources;
-        solution = solution * step / solution.gcd(step);
+        network.press(|_, pulse| {
+            if pulse.destination == target && !pulse.state {
+                reached = true;
+            } else if Some(pulse.destination) == conjunction && pulse.state {
+                let index = inputs.iter().position(|&input| input == pulse.source);
+                let highs: &mut Vec<u64> = &mut highs[index.unwrap()];
 
-        if seen == required {
-            break Ok(solution);
+                if highs.last() != Some(&press) {
+                    highs.push(press);
+                }
+            }
+        });
+
+        if reached {
+            return Ok(press);
+        }
+
+        if let Some(conjunction) = conjunction {
+            if highs.iter().all(|highs| highs.len() >= 2) {
+                return network.combine_intervals(conjunction, &inputs, &highs);
+            }
         }
     }
+
+    anyhow::bail!("Module {name} did not receive a low pulse after {MAX_PRESSES} presses")
 }
 
-fn simulate(
-    connections: &mut [Connection],
-    queue: &mut VecDeque<Pulse>,
-    monitor: usize,
-) -> (usize, usize, BitSet) {
-    queue.push_front(Pulse::default());
+struct Network<'a> {
+    // the modules by name, the broadcaster being the first one
+    modules: IndexMapBuilder<&'a [u8], Connection>,
+    queue: VecDeque<Pulse>,
+}
 
-    let mut low_count = 0;
-    let mut hight_count = 0;
-    let mut sources = BitSet::default();
-
-    while let Some(pulse) = queue.pop_front() {
-        if pulse.state {
-            hight_count += 1;
-        } else {
-            low_count += 1;
+impl<'a> Network<'a> {
+    fn parse(input: &[&'a str]) -> Self {
+        let mut builder = IndexMapBuilder::<&[u8], Connection>::with_capacity(input.len());
+        builder.reserve(b"broadcaster");
+
+        for line in input.iter() {
+            let mut parts = line.as_bytes().split(|char| *char == b' ');
+            let source = parts.next().unwrap();
+            parts.next(); // skip "->"
+
+            let module = match source[0] {
+                b'b' => Module::Broadcaster,
+                b'%' => Module::FlipFlop { state: false },
+                b'&' => Module::Conjunction {
+                    active: BitSet::default(),
+                    required: BitSet::default(),
+                },
+                _ => unreachable!(),
+            };
+
+            let id = builder.find_index(get_id(source));
+
+            let children = parts
+                .map(|child| builder.find_index(get_id(child)))
+                .collect();
+
+            builder[id] = Connection { module, children };
         }
 
-        let state = match &mut connections[pulse.destination].module {
-            Module::Broadcaster => pulse.state,
-            Module::FlipFlop { .. } if pulse.state => continue,
-            Module::FlipFlop { state } => {
-                *state = !*state;
-                *state
-            }
-            Module::Conjunction { active, required } => {
-                if pulse.state && pulse.destination == monitor {
-                    sources = sources.set(pulse.source);
+        for i in 0..builder.len() {
+            for child in builder[i].children.clone().iter() {
+                if let Module::Conjunction { required, .. } = &mut builder[child].module {
+                    required.insert(i);
                 }
+            }
+        }
 
-                *active = if pulse.state {
-                    active.set(pulse.source)
-                } else {
-                    active.unset(pulse.source)
-                };
+        Self {
+            modules: builder,
+            queue: VecDeque::new(),
+        }
+    }
 
-                active != required
-            }
+    fn find(&self, name: &[u8]) -> Option<usize> {
+        self.modules.get_index(name)
+    }
+
+    // Modules which send pulses to the module
+    fn inputs(&self, module: usize) -> Vec<usize> {
+        (0..self.modules.len())
+            .filter(|&i| self.modules[i].children.contains(module))
+            .collect()
+    }
+
+    fn is_conjunction(&self, module: usize) -> bool {
+        matches!(self.modules[module].module, Module::Conjunction { .. })
+    }
+
+    // Formats the pulse the same way as the puzzle, e.g. "a -high-> b"
+    fn describe(&self, pulse: &Pulse) -> String {
+        let source = match self.modules.key(pulse.source) {
+            Some(name) => String::from_utf8_lossy(name),
+            None => "button".into(),
         };
 
-        for child in connections[pulse.destination].children {
-            queue.push_back(Pulse {
-                source: pulse.destination,
-                destination: child,
-                state,
-            });
+        let destination = String::from_utf8_lossy(self.modules.keys()[pulse.destination]);
+        let state = if pulse.state { "high" } else { "low" };
+        format!("{source} -{state}-> {destination}")
+    }
+
+    // Presses the button and processes pulses until the network settles,
+    // passing every pulse to the observer before it's handled
+    fn press(&mut self, mut observer: impl FnMut(&Self, &Pulse)) {
+        self.queue.push_back(Pulse::BUTTON);
+
+        while let Some(pulse) = self.queue.pop_front() {
+            observer(self, &pulse);
+
+            let state = match &mut self.modules[pulse.destination].module {
+                Module::Broadcaster => pulse.state,
+                Module::Output => continue,
+                Module::FlipFlop { .. } if pulse.state => continue,
+                Module::FlipFlop { state } => {
+                    *state = !*state;
+                    *state
+                }
+                Module::Conjunction { active, required } => {
+                    if pulse.state {
+                        active.insert(pulse.source);
+                    } else {
+                        active.remove(pulse.source);
+                    }
+
+                    active != required
+                }
+            };
+
+            for child in self.modules[pulse.destination].children.iter() {
+                self.queue.push_back(Pulse {
+                    source: pulse.destination,
+                    destination: child,
+                    state,
+                });
+            }
         }
     }
 
-    (low_count, hight_count, sources)
-}
+    // Renders the network in the DOT format, with each kind of module styled differently and
+    // the modules of each loop grouped into a cluster
+    fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph network {\n");
 
-fn parse(input: &[&str]) -> Vec<Connection> {
-    // +1 because rx doesn't have its own row
-    let mut builder = IndexMapBuilder::<&[u8], Connection>::with_capacity(input.len() + 1);
-    builder.reserve(b"broadcaster");
-    builder.reserve(b"rx");
-
-    for line in input.iter() {
-        let mut parts = line.as_bytes().split(|char| *char == b' ');
-        let source = parts.next().unwrap();
-        parts.next(); // skip "->"
-
-        let module = match source[0] {
-            b'b' => Module::Broadcaster,
-            b'%' => Module::FlipFlop { state: false },
-            b'&' => Module::Conjunction {
-                active: BitSet::default(),
-                required: BitSet::default(),
-            },
-            _ => unreachable!(),
-        };
+        for (i, modules) in self.find_loops().iter().enumerate() {
+            _ = writeln!(dot, "    subgraph cluster_{i} {{");
+            _ = writeln!(dot, "        label=\"loop {}\";", i + 1);
 
-        let id = builder.find_index(get_id(source));
+            for &id in modules {
+                _ = writeln!(dot, "        {};", self.dot_id(id));
+            }
 
-        let children = parts
-            .map(|child| builder.find_index(get_id(child)))
-            .fold(BitSet::default(), |set, id| set.set(id));
+            dot.push_str("    }\n");
+        }
 
-        builder[id] = Connection { module, children };
-    }
+        for (name, id, connection) in self.modules.iter() {
+            let (prefix, style) = match connection.module {
+                Module::Output => ("", "shape=doubleoctagon,style=filled,fillcolor=gold"),
+                Module::Broadcaster => ("", "shape=house,style=filled,fillcolor=palegreen"),
+                Module::FlipFlop { .. } => ("%", "shape=box"),
+                Module::Conjunction { .. } => ("&", "style=filled,fillcolor=lightblue"),
+            };
+
+            let name = String::from_utf8_lossy(name);
+            let id = self.dot_id(id);
+            _ = writeln!(dot, "    {id} [label=\"{prefix}{name}\",{style}];");
+        }
 
-    for i in 0..builder.len() {
-        for child in builder[i].children {
-            if let Module::Conjunction { required, .. } = &mut builder[child].module {
-                *required = required.set(i);
+        for (_, id, connection) in self.modules.iter() {
+            for child in connection.children.iter() {
+                _ = writeln!(dot, "    {} -> {};", self.dot_id(id), self.dot_id(child));
             }
         }
+
+        dot.push('}');
+        dot.push('\n');
+        dot
+    }
+
+    fn dot_id(&self, module: usize) -> String {
+        format!(
+            "\"{}\"",
+            String::from_utf8_lossy(self.modules.keys()[module])
+        )
     }
 
-    builder.build()
+    // Finds the groups of modules which feed back into each other (like the puzzle's counters),
+    // which are the strongly connected components with more than one module
+    fn find_loops(&self) -> Vec<Vec<usize>> {
+        let mut loops = graph::strongly_connected_components(self.modules.len(), |id| {
+            self.modules[id].children.iter()
+        });
+
+        loops.retain(|modules| modules.len() > 1);
+        loops.reverse();
+        loops
+    }
+
+    // Computes the first press at which all inputs of the conjunction send a high pulse,
+    // given the first two presses at which each of them sent one
+    fn combine_intervals(
+        &self,
+        conjunction: usize,
+        inputs: &[usize],
+        highs: &[Vec<u64>],
+    ) -> anyhow::Result<u64> {
+        let mut solution = 1u64;
+
+        for (&input, highs) in inputs.iter().zip(highs) {
+            let (first, second) = (highs[0], highs[1]);
+
+            if second != 2 * first {
+                anyhow::bail!(
+                    "{} sent high pulses to {} after {first} and {second} presses, \
+                    which is not an exact interval, so the presses can't be computed using LCM",
+                    String::from_utf8_lossy(self.modules.keys()[input]),
+                    String::from_utf8_lossy(self.modules.keys()[conjunction]),
+                );
+            }
+
+            solution = solution * first / solution.gcd(first);
+        }
+
+        Ok(solution)
+    }
 }
 
 fn get_id(value: &[u8]) -> &[u8] {
@@ -177,7 +321,9 @@ struct Connection {
 
 #[derive(Clone, Eq, PartialEq, Default)]
 enum Module {
+    // Modules which only receive pulses, like rx
     #[default]
+    Output,
     Broadcaster,
     FlipFlop {
         state: bool,
@@ -188,64 +334,123 @@ enum Module {
     },
 }
 
-#[derive(Default)]
 struct Pulse {
     source: usize,
     destination: usize,
     state: bool,
 }
 
-#[derive(Copy, Clone, Eq, PartialEq, Default)]
-struct BitSet(u64);
-
-impl BitSet {
-    fn is_empty(self) -> bool {
-        self.0 == 0
-    }
-
-    fn has(self, value: usize) -> bool {
-        self.0 & (1 << value) != 0
-    }
+impl Pulse {
+    // The low pulse sent by the button to the broadcaster
+    const BUTTON: Self = Self {
+        source: usize::MAX,
+        destination: 0,
+        state: false,
+    };
+}
 
-    fn set(self, value: usize) -> Self {
-        Self(self.0 | (1 << value))
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE_A: &[&str] = &[
+        "broadcaster -> a, b, c",
+        "%a -> b",
+        "%b -> c",
+        "%c -> inv",
+        "&inv -> a",
+    ];
+
+    const EXAMPLE_B: &[&str] = &[
+        "broadcaster -> a",
+        "%a -> inv, con",
+        "&inv -> b",
+        "%b -> con",
+        "&con -> output",
+    ];
+
+    fn press(network: &mut Network) -> Vec<String> {
+        let mut pulses = Vec::new();
+        network.press(|network, pulse| pulses.push(network.describe(pulse)));
+        pulses
     }
 
-    fn unset(self, value: usize) -> Self {
-        Self(self.0 & !(1 << value))
+    #[test]
+    fn examples() {
+        let args = Args::default();
+        assert_eq!(part_a(EXAMPLE_A, &args).unwrap().to_string(), "32000000");
+        assert_eq!(part_a(EXAMPLE_B, &args).unwrap().to_string(), "11687500");
     }
-}
 
-impl BitOrAssign for BitSet {
-    fn bitor_assign(&mut self, rhs: Self) {
-        self.0 |= rhs.0
+    #[test]
+    fn pulses() {
+        let mut network = Network::parse(EXAMPLE_B);
+
+        assert_eq!(
+            press(&mut network),
+            [
+                "button -low-> broadcaster",
+                "broadcaster -low-> a",
+                "a -high-> inv",
+                "a -high-> con",
+                "inv -low-> b",
+                "con -high-> output",
+                "b -high-> con",
+                "con -low-> output",
+            ]
+        );
+
+        assert_eq!(
+            press(&mut network),
+            [
+                "button -low-> broadcaster",
+                "broadcaster -low-> a",
+                "a -low-> inv",
+                "a -low-> con",
+                "inv -high-> b",
+                "con -high-> output",
+            ]
+        );
     }
-}
 
-impl IntoIterator for BitSet {
-    type Item = usize;
-
-    type IntoIter = BitSetIterator;
-
-    fn into_iter(self) -> Self::IntoIter {
-        BitSetIterator(self.0)
+    #[test]
+    fn combine_intervals() {
+        let network = Network::parse(&["broadcaster -> a, b", "&a -> z", "&b -> z", "&z -> rx"]);
+        let z = network.find(b"z").unwrap();
+        let inputs = network.inputs(z);
+
+        // z receives a high pulse from a every 4 presses and from b every 6 presses
+        let highs = [vec![4, 8], vec![6, 12]];
+        assert_eq!(network.combine_intervals(z, &inputs, &highs).unwrap(), 12);
+
+        let highs = [vec![4, 8], vec![6, 13]];
+        assert_eq!(
+            network
+                .combine_intervals(z, &inputs, &highs)
+                .unwrap_err()
+                .to_string(),
+            "b sent high pulses to z after 6 and 13 presses, \
+            which is not an exact interval, so the presses can't be computed using LCM"
+        );
     }
-}
-
-struct BitSetIterator(u64);
-
-impl Iterator for BitSetIterator {
-    type Item = usize;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.0 == 0 {
-            return None;
-        }
-
-        let lowest_bit = self.0 & 0u64.wrapping_sub(self.0);
-        let item = lowest_bit.trailing_zeros();
-        self.0 ^= lowest_bit;
 
-        Some(item as usize)
+    #[test]
+    fn inputs_without_exact_intervals() {
+        // inv sends high pulses on the 3rd and 7th presses, but never while w is high
+        let input = &[
+            "broadcaster -> a",
+            "%a -> k, b",
+            "%b -> k, w",
+            "&k -> inv",
+            "&inv -> z",
+            "&w -> z",
+            "&z -> rx",
+        ];
+
+        assert_eq!(
+            part_b(input, &Args::default()).err().unwrap().to_string(),
+            "inv sent high pulses to z after 3 and 7 presses, \
+            which is not an exact interval, so the presses can't be computed using LCM"
+        );
     }
 }
@@ -1,7 +1,42 @@
-use rustc_hash::FxHashSet;
+use crate::args::Args;
+use gcd::Gcd;
 
-pub fn part_a(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
-    Ok(simulate(input, 64, Quadrant::All).len())
+const SAMPLES: usize = 16;
+// Number of trailing second differences of the samples which have to agree before extrapolating
+const STABLE_DIFFERENCES: usize = 8;
+const MAX_SIMULATED_STEPS: usize = 5000;
+// Limits the size of a frontier to 2 * 128MB
+const MAX_FRONTIER_PLOTS: usize = 1 << 30;
+
+// Passing step counts, e.g. `21a 6 10 50`, counts the reachable plots after each of them instead
+// of the puzzle's step count
+pub fn part_a(input: &[&str], args: &Args) -> anyhow::Result<impl std::fmt::Display> {
+    count_reachable(input, args, 64)
+}
+
+pub fn part_b(input: &[&str], args: &Args) -> anyhow::Result<impl std::fmt::Display> {
+    count_reachable(input, args, 26501365)
+}
+
+fn count_reachable(input: &[&str], args: &Args, default_steps: usize) -> anyhow::Result<String> {
+    let garden = Garden::parse(input)?;
+    let steps = args.positional::<usize>()?;
+
+    if steps.is_empty() {
+        return Ok(garden.count_reachable(default_steps)?.to_string());
+    }
+
+    let counts = steps
+        .iter()
+        .map(|&steps| {
+            Ok(format!(
+                "{} after {steps} steps",
+                garden.count_reachable(steps)?
+            ))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    Ok(counts.join(", "))
 }
 
 // Once we've reached the edge of the 2rd grid (counting from 0) a pattern starts to emerge:
@@ -18,7 +53,7 @@ pub fn part_a(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
 //  - - - - -
 // A, B, C, ... are arrangements of the potential steps
 //
-// Our grid has a size of 131.
+// The puzzle's grid has a size of 131.
 // We reach the edge of it for the first time (due to starting at the center) at 65 steps.
 // 26501365 steps gets us exactly to the edge of the (26501365 - 65) / 131 = 202300th grid.
 //
@@ -38,11 +73,12 @@ pub fn part_a(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
 //
 // Finally the K, L, M and N grids repeat exactly as well.
 // Each of them only shows up once.
-pub fn part_b(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
-    const STEPS: u64 = 26501365;
-
-    // assume the grid is square
-    let size = input.len() as isize;
+//
+// This only works when the garden is square, the start is at its center and the start's row and
+// column, as well as the garden's edges, don't contain any rocks. The steps also have to end at
+// the edge of a grid.
+fn count_diamond(garden: &Garden, steps: u64) -> anyhow::Result<u64> {
+    let size = garden.width as isize;
     let center = size / 2;
 
     // size / 2 gets us to the edge of the first grid, each additinal size steps gets us
@@ -50,15 +86,20 @@ pub fn part_b(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
     // This is just enough to observe all of the unique grid arrangements.
     let min_steps = (2 * size + size / 2) as usize;
 
-    let top_left = simulate(input, min_steps, Quadrant::TopLeft).into_iter();
-    let top_right = simulate(input, min_steps, Quadrant::TopRight).into_iter();
-    let bottom_left = simulate(input, min_steps, Quadrant::BottomLeft).into_iter();
-    let bottom_right = simulate(input, min_steps, Quadrant::BottomRight).into_iter();
+    let top_left = simulate(garden, min_steps, Quadrant::TopLeft)?;
+    let top_right = simulate(garden, min_steps, Quadrant::TopRight)?;
+    let bottom_left = simulate(garden, min_steps, Quadrant::BottomLeft)?;
+    let bottom_right = simulate(garden, min_steps, Quadrant::BottomRight)?;
 
     let positions = top_left
-        .chain(top_right.filter(|(x, _)| *x != center))
-        .chain(bottom_left.filter(|(_, y)| *y != center))
-        .chain(bottom_right.filter(|(x, y)| *x != center && *y != center));
+        .positions()
+        .chain(top_right.positions().filter(|(x, _)| *x != center))
+        .chain(bottom_left.positions().filter(|(_, y)| *y != center))
+        .chain(
+            bottom_right
+                .positions()
+                .filter(|(x, y)| *x != center && *y != center),
+        );
 
     let mut center_a = 0u64; // A grids
     let mut center_b = 0u64; // B grids
@@ -78,7 +119,7 @@ pub fn part_b(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
     }
 
     // The number (starting from 0) of the grid we've reached horizontally or vertically
-    let count = STEPS / size as u64;
+    let count = steps / size as u64;
 
     Ok(center_a * (count - 1).pow(2)
         + center_b * count.pow(2)
@@ -87,74 +128,282 @@ pub fn part_b(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
         + corners)
 }
 
-fn simulate(input: &[&str], steps: usize, quadrant: Quadrant) -> FxHashSet<(isize, isize)> {
-    // assume the grid is square
-    let size = input.len() as isize;
-    // assume the starting position is at the center
-    let start = (size / 2, size / 2);
-
-    let mut current_steps = FxHashSet::default();
-    let mut next_steps = FxHashSet::default();
-    let mut frozen_steps = FxHashSet::default();
-    current_steps.insert(start);
-
-    // Once a grid is fully "discovered" it starts swapping between 2 arrangements.
-    // So we can freeze them in the "correct" state to improve performance.
-    let center_freeze = (size - 1) as usize;
-    let extended_freeze = 2 * center_freeze;
-
-    let is_frozen = |step: usize, x: isize, y: isize| -> bool {
-        if step > extended_freeze {
-            // freezes the center grid and the adjacent (top, bottom, left, right) grids
-            ((-131..262).contains(&x) && (0..131).contains(&y))
-                || ((0..131).contains(&x) && (-131..262).contains(&y))
-        } else if step > center_freeze {
-            // freezes the center grid
-            (0..131).contains(&x) && (0..131).contains(&y)
-        } else {
-            false
+fn simulate(garden: &Garden, steps: usize, quadrant: Quadrant) -> anyhow::Result<Frontier> {
+    let mut frontier = Frontier::new(garden, steps, quadrant)?;
+
+    for _ in 0..steps {
+        frontier.step();
+    }
+
+    Ok(frontier)
+}
+
+struct Garden<'a> {
+    rows: &'a [&'a str],
+    width: usize,
+    height: usize,
+    start: (isize, isize),
+}
+
+impl<'a> Garden<'a> {
+    fn parse(input: &'a [&'a str]) -> anyhow::Result<Self> {
+        let width = input.first().map_or(0, |row| row.len());
+        let height = input.len();
+
+        if width == 0 || input.iter().any(|row| row.len() != width) {
+            anyhow::bail!("The garden's rows must all have the same, non-zero, length");
+        }
+
+        let start = input
+            .iter()
+            .enumerate()
+            .find_map(|(y, row)| row.find('S').map(|x| (x as isize, y as isize)));
+
+        let start = match start {
+            Some(start) => start,
+            None => anyhow::bail!("The garden does not have a starting position"),
+        };
+
+        Ok(Self {
+            rows: input,
+            width,
+            height,
+            start,
+        })
+    }
+
+    // The garden repeats infinitely in every direction
+    fn is_rock(&self, x: isize, y: isize) -> bool {
+        let x = x.rem_euclid(self.width as isize) as usize;
+        let y = y.rem_euclid(self.height as isize) as usize;
+        self.rows[y].as_bytes()[x] == b'#'
+    }
+
+    fn count_reachable(&self, steps: usize) -> anyhow::Result<u64> {
+        let size = self.width;
+
+        if self.has_diamond_structure() && steps % size == size / 2 && steps / size >= 2 {
+            return count_diamond(self, steps as u64);
+        }
+
+        // Once the reached area spans a few gardens, it grows quadratically in the number of
+        // gardens. So sampling the counts in intervals of the garden's size (or both sizes if it
+        // isn't square) shows a constant second difference, which is used to extrapolate the count.
+        let period = self.width / self.width.gcd(self.height) * self.height;
+        let remainder = steps % period;
+        let simulated = remainder + (SAMPLES - 1) * period;
+
+        if steps <= simulated && steps <= MAX_SIMULATED_STEPS {
+            return Ok(self.count_by_step(steps)?[steps]);
+        }
+
+        if simulated > MAX_SIMULATED_STEPS {
+            anyhow::bail!(
+                "The garden repeats every {period} steps, so sampling the reachable plots takes \
+                {simulated} steps, more than the limit of {MAX_SIMULATED_STEPS}"
+            );
+        }
+
+        let counts = self.count_by_step(simulated)?;
+        let samples = (0..SAMPLES)
+            .map(|i| counts[remainder + i * period] as i64)
+            .collect::<Vec<_>>();
+
+        let differences = samples
+            .windows(3)
+            .map(|window| window[2] - 2 * window[1] + window[0])
+            .collect::<Vec<_>>();
+
+        let stable = differences[differences.len() - STABLE_DIFFERENCES..]
+            .windows(2)
+            .all(|window| window[0] == window[1]);
+
+        if !stable {
+            // The growth isn't quadratic (yet), so the only option is to simulate every step
+            if steps <= MAX_SIMULATED_STEPS {
+                return Ok(self.count_by_step(steps)?[steps]);
+            }
+
+            anyhow::bail!(
+                "The reachable plots don't grow quadratically and {steps} steps are too many \
+                to simulate (the limit is {MAX_SIMULATED_STEPS})"
+            );
+        }
+
+        let second = differences[differences.len() - 1];
+        let first = samples[SAMPLES - 1] - samples[SAMPLES - 2];
+        let n = ((steps - simulated) / period) as i64;
+        Ok((samples[SAMPLES - 1] + n * first + second * n * (n + 1) / 2) as u64)
+    }
+
+    // The structure of the puzzle's gardens, which makes it possible to use `count_diamond`
+    fn has_diamond_structure(&self) -> bool {
+        let size = self.width as isize;
+        let center = size / 2;
+
+        self.width == self.height
+            && self.width % 2 == 1
+            && self.start == (center, center)
+            && (0..size).all(|i| {
+                !self.is_rock(i, center)
+                    && !self.is_rock(center, i)
+                    && !self.is_rock(i, 0)
+                    && !self.is_rock(0, i)
+                    && !self.is_rock(i, size - 1)
+                    && !self.is_rock(size - 1, i)
+            })
+    }
+
+    // Counts the reachable plots after each number of steps
+    fn count_by_step(&self, steps: usize) -> anyhow::Result<Vec<u64>> {
+        let mut frontier = Frontier::new(self, steps, Quadrant::All)?;
+        let mut counts = Vec::with_capacity(steps + 1);
+        counts.push(frontier.count());
+
+        for _ in 0..steps {
+            frontier.step();
+            counts.push(frontier.count());
+        }
+
+        Ok(counts)
+    }
+}
+
+// The plots reached after some number of steps, within the part of the repeating garden that
+// can be reached from the start (limited to a quadrant).
+// Each row is stored as bits, so a step only takes a few shifts, ors and ands per 64 plots.
+struct Frontier {
+    left: isize,
+    top: isize,
+    width: usize,
+    height: usize,
+    // number of words per row
+    words: usize,
+    current: Vec<u64>,
+    next: Vec<u64>,
+    // The plots of each of the garden's rows, repeated over the width of the frontier
+    plots: Vec<u64>,
+    start: (usize, usize),
+    steps: usize,
+}
+
+impl Frontier {
+    fn new(garden: &Garden, reach: usize, quadrant: Quadrant) -> anyhow::Result<Self> {
+        let side = match quadrant {
+            Quadrant::All => reach.saturating_mul(2).saturating_add(1),
+            _ => reach.saturating_add(1),
+        };
+
+        if side.saturating_mul(side) > MAX_FRONTIER_PLOTS {
+            anyhow::bail!(
+                "Reaching {reach} steps away from the start requires tracking {side}x{side} plots, \
+                more than the limit of {MAX_FRONTIER_PLOTS}"
+            );
         }
-    };
 
-    for step in 0..steps {
-        for (x, y) in current_steps.drain() {
-            if is_frozen(step, x, y) {
-                frozen_steps.insert((x, y));
-                continue;
+        let reach = reach as isize;
+        let (x, y) = garden.start;
+
+        let (left, right, top, bottom) = match quadrant {
+            Quadrant::All => (x - reach, x + reach, y - reach, y + reach),
+            Quadrant::TopLeft => (x - reach, x, y - reach, y),
+            Quadrant::TopRight => (x, x + reach, y - reach, y),
+            Quadrant::BottomLeft => (x - reach, x, y, y + reach),
+            Quadrant::BottomRight => (x, x + reach, y, y + reach),
+        };
+
+        let width = (right - left + 1) as usize;
+        let height = (bottom - top + 1) as usize;
+        let words = width.div_ceil(64);
+        let mut plots = vec![0; garden.height * words];
+
+        for (row, plots) in plots.chunks_mut(words).enumerate() {
+            for column in 0..width {
+                if !garden.is_rock(left + column as isize, row as isize) {
+                    plots[column / 64] |= 1 << (column % 64);
+                }
             }
+        }
 
-            for (dx, dy) in [(1, 0), (0, 1), (-1, 0), (0, -1)] {
-                let x = x + dx;
-                let y = y + dy;
+        let start = ((x - left) as usize, (y - top) as usize);
+        let mut current = vec![0; height * words];
+        current[start.1 * words + start.0 / 64] = 1 << (start.0 % 64);
 
-                match quadrant {
-                    Quadrant::TopLeft if x > start.0 || y > start.1 => continue,
-                    Quadrant::TopRight if x < start.0 || y > start.1 => continue,
-                    Quadrant::BottomLeft if x > start.0 || y < start.1 => continue,
-                    Quadrant::BottomRight if x < start.0 || y < start.1 => continue,
-                    _ => {}
+        Ok(Self {
+            left,
+            top,
+            width,
+            height,
+            words,
+            next: current.clone(),
+            current,
+            plots,
+            start,
+            steps: 0,
+        })
+    }
+
+    fn step(&mut self) {
+        self.steps += 1;
+
+        // Only the plots which are at most `steps` away from the start can be reached
+        let rows =
+            self.start.1.saturating_sub(self.steps)..self.height.min(self.start.1 + self.steps + 1);
+        let first_word = self.start.0.saturating_sub(self.steps) / 64;
+        let last_word = (self.width - 1).min(self.start.0 + self.steps) / 64;
+        let garden_height = self.plots.len() / self.words;
+
+        for y in rows {
+            let garden_y = (self.top + y as isize).rem_euclid(garden_height as isize) as usize;
+
+            for w in first_word..=last_word {
+                let i = y * self.words + w;
+                let word = self.current[i];
+
+                // Moving right shifts bits up, moving left shifts them down
+                let mut reached = word << 1 | word >> 1;
+
+                if w > 0 {
+                    reached |= self.current[i - 1] >> 63;
                 }
 
-                if is_frozen(step, x, y) {
-                    continue;
+                if w + 1 < self.words {
+                    reached |= self.current[i + 1] << 63;
                 }
 
-                let gx = x.rem_euclid(size) as usize;
-                let gy = y.rem_euclid(size) as usize;
+                if y > 0 {
+                    reached |= self.current[i - self.words];
+                }
 
-                if input[gy].as_bytes()[gx] == b'#' {
-                    continue;
+                if y + 1 < self.height {
+                    reached |= self.current[i + self.words];
                 }
 
-                next_steps.insert((x, y));
+                self.next[i] = reached & self.plots[garden_y * self.words + w];
             }
         }
 
-        (current_steps, next_steps) = (next_steps, current_steps);
+        std::mem::swap(&mut self.current, &mut self.next);
     }
 
-    current_steps.extend(frozen_steps);
-    current_steps
+    fn count(&self) -> u64 {
+        self.current
+            .iter()
+            .map(|word| word.count_ones() as u64)
+            .sum()
+    }
+
+    fn positions(&self) -> impl Iterator<Item = (isize, isize)> + '_ {
+        self.current.iter().enumerate().flat_map(move |(i, &word)| {
+            (0..64)
+                .filter(move |bit| word & (1 << bit) != 0)
+                .map(move |bit| {
+                    let x = (i % self.words * 64 + bit) as isize;
+                    let y = (i / self.words) as isize;
+                    (self.left + x, self.top + y)
+                })
+        })
+    }
 }
 
 fn grid_index(position: isize, size: isize) -> isize {
@@ -172,3 +421,56 @@ enum Quadrant {
     BottomLeft,
     BottomRight,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &[&str] = &[
+        "...........",
+        ".....###.#.",
+        ".###.##..#.",
+        "..#.#...#..",
+        "....#.#....",
+        ".##..S####.",
+        ".##..#...#.",
+        ".......##..",
+        ".##.#.####.",
+        ".##..##.##.",
+        "...........",
+    ];
+
+    #[test]
+    fn example_counts() {
+        let garden = Garden::parse(EXAMPLE).unwrap();
+        let expected = [
+            (6, 16),
+            (10, 50),
+            (50, 1594),
+            (100, 6536),
+            (500, 167004),
+            (1000, 668697),
+            (5000, 16733044),
+        ];
+
+        for (steps, count) in expected {
+            assert_eq!(
+                garden.count_reachable(steps).unwrap(),
+                count,
+                "{steps} steps"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_gardens_repeating_too_slowly_to_sample() {
+        // the 131x130 garden only repeats every 17030 steps
+        let mut rows = vec![".".repeat(131); 130];
+        rows[65].replace_range(65..66, "S");
+        let rows = rows.iter().map(String::as_str).collect::<Vec<_>>();
+        let garden = Garden::parse(&rows).unwrap();
+
+        assert_eq!(garden.count_reachable(100).unwrap(), 101 * 101);
+        assert!(garden.count_reachable(26501365).is_err());
+    }
+}
@@ -1,37 +1,22 @@
-use crate::utils::Bytes;
-use rustc_hash::FxHashSet;
-use std::cmp::Ordering;
+use crate::{
+    args::Args,
+    utils::{BitSet, Bytes},
+};
+use rustc_hash::FxHashMap;
 
-const GRID_SIZE: usize = 10;
+// Footprints larger than this use a sparse height map
+const MAX_DENSE_AREA: usize = 1 << 20;
+// Settling a brick visits every position of its footprint, so larger bricks are rejected
+const MAX_FOOTPRINT: usize = 1 << 20;
 
 pub fn part_a(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
-    let bricks = parse_bricks(input);
-    // top down view of the grid
-    let mut grid = [Point::default(); GRID_SIZE * GRID_SIZE];
+    let bricks = parse_bricks(input)?;
+    let supports = settle(&bricks);
     let mut unstable = vec![false; bricks.len()];
 
-    for (id, brick) in bricks.into_iter().enumerate() {
-        // z position at which the brick will land
-        let mut max_z = 0;
-        // id/index of the *single* brick supporting the current brick (or usize::MAX)
-        let mut support_id = usize::MAX;
-
-        for index in brick.indexes() {
-            match grid[index].z.cmp(&max_z) {
-                Ordering::Greater => {
-                    max_z = grid[index].z;
-                    support_id = grid[index].brick;
-                }
-                Ordering::Equal if grid[index].brick != support_id => {
-                    support_id = usize::MAX;
-                }
-                _ => {}
-            }
-        }
-
-        update_grid(id, brick, max_z, &mut grid);
-
-        if support_id != usize::MAX {
+    for supports in supports.iter() {
+        // removing the *single* brick supporting a brick would cause it to fall
+        if let [support_id] = supports[..] {
             unstable[support_id] = true;
         }
     }
@@ -39,96 +24,216 @@ pub fn part_a(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
     Ok(unstable.iter().filter(|unstable| !**unstable).count())
 }
 
-pub fn part_b(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
-    let bricks = parse_bricks(input);
-    // top down view of the grid
-    let mut grid = [Point::default(); GRID_SIZE * GRID_SIZE];
-    // ids/indexes of bricks that cause each brick to fall
-    let mut falls_when = Vec::<FxHashSet<usize>>::with_capacity(bricks.len());
-    // ids/indexes of bricks supporting the current brick (only used inside the loop)
-    let mut supports = FxHashSet::default();
+// Removing a brick causes exactly the bricks it dominates (in the dominator tree of the support
+// graph, rooted at the ground) to fall. So the number of falling bricks is the size of its subtree.
+//
+// Passing `remove=<brick>` lists the bricks which fall when removing a single brick instead,
+// where bricks are identified by their line in the input (counting from 0)
+pub fn part_b(input: &[&str], args: &Args) -> anyhow::Result<impl std::fmt::Display> {
+    let bricks = parse_bricks(input)?;
+    let dominators = Dominators::new(&settle(&bricks));
+
+    let Some(line) = args.get::<usize>("remove")? else {
+        let total = (0..bricks.len())
+            .map(|id| dominators.subtree_size(id) - 1)
+            .sum::<usize>();
 
-    for (id, brick) in bricks.into_iter().enumerate() {
+        return Ok(total.to_string());
+    };
+
+    let id = match bricks.iter().position(|brick| brick.line == line) {
+        Some(id) => id,
+        None => anyhow::bail!(
+            "Brick {line} does not exist, there are {} bricks",
+            bricks.len()
+        ),
+    };
+
+    let mut falling = dominators
+        .dominated(id)
+        .into_iter()
+        .map(|id| bricks[id].line)
+        .collect::<Vec<_>>();
+
+    falling.sort_unstable();
+
+    let lines = falling
+        .iter()
+        .map(|line| line.to_string())
+        .collect::<Vec<_>>();
+
+    Ok(format!("{} ({})", falling.len(), lines.join(", ")))
+}
+
+// Drops the bricks (sorted by their lowest z position) and returns the ids/indexes of the bricks
+// each brick lands on
+fn settle(bricks: &[Brick]) -> Vec<Vec<usize>> {
+    let mut heights = HeightMap::new(bricks);
+    let mut supports = Vec::with_capacity(bricks.len());
+
+    for (id, brick) in bricks.iter().enumerate() {
         // z position at which the brick will land
         let mut max_z = 0;
+        // ids/indexes of bricks supporting the current brick
+        let mut current = Vec::new();
 
-        for index in brick.indexes() {
-            if grid[index].z > max_z {
-                max_z = grid[index].z;
-                supports.clear();
+        for (x, y) in brick.positions() {
+            let point = heights.get(x, y);
+
+            if point.z > max_z {
+                max_z = point.z;
+                current.clear();
             }
 
-            if max_z != 0 && max_z == grid[index].z {
-                supports.insert(grid[index].brick);
+            if max_z != 0 && max_z == point.z && !current.contains(&point.brick) {
+                current.push(point.brick);
             }
         }
 
-        update_grid(id, brick, max_z, &mut grid);
+        let point = Point {
+            brick: id,
+            z: max_z + brick.z1 - brick.z0 + 1,
+        };
 
-        if max_z == 0 {
-            // supports might contain stale ids from the previous iteration
-            supports.clear();
+        for (x, y) in brick.positions() {
+            heights.set(x, y, point);
         }
 
-        falls_when.push(find_load_bearing_bricks(&supports, &falls_when));
+        supports.push(current);
     }
 
-    Ok(falls_when.into_iter().map(|x| x.len()).sum::<usize>())
+    supports
 }
 
-fn update_grid(id: usize, brick: Brick, max_z: usize, grid: &mut [Point; GRID_SIZE * GRID_SIZE]) {
-    let height = brick.z1 - brick.z0 + 1;
-    let next_z = max_z + height;
+// The dominator tree of the support graph, built incrementally as the bricks land.
+// A brick's parent is the lowest common ancestor of the bricks supporting it (or the ground),
+// because removing any other brick leaves at least one of its supports in place.
+// Nodes are the brick ids shifted by one, the ground being node 0.
+struct Dominators {
+    parents: Vec<usize>,
+    depths: Vec<usize>,
+    // The 1st, 2nd, 4th, 8th, ... ancestors of each node (binary lifting)
+    ancestors: Vec<Vec<usize>>,
+    // Number of nodes in the subtree of each node, including the node itself
+    sizes: Vec<usize>,
+}
 
-    for index in brick.indexes() {
-        grid[index] = Point {
-            brick: id,
-            z: next_z,
+impl Dominators {
+    const GROUND: usize = 0;
+
+    fn new(supports: &[Vec<usize>]) -> Self {
+        let mut tree = Self {
+            parents: vec![Self::GROUND],
+            depths: vec![0],
+            ancestors: vec![Vec::new()],
+            sizes: Vec::new(),
         };
+
+        for supports in supports {
+            // supports always landed before the brick, so they're already part of the tree
+            let parent = supports
+                .iter()
+                .map(|id| id + 1)
+                .reduce(|a, b| tree.lowest_common_ancestor(a, b))
+                .unwrap_or(Self::GROUND);
+
+            tree.push(parent);
+        }
+
+        // parents always come before their children
+        tree.sizes = vec![1; tree.parents.len()];
+
+        for node in (1..tree.parents.len()).rev() {
+            tree.sizes[tree.parents[node]] += tree.sizes[node];
+        }
+
+        tree
     }
-}
 
-// A brick will fall when removing a brick that causes all of its supporting bricks to fall as well
-fn find_load_bearing_bricks(
-    supports: &FxHashSet<usize>,
-    falls_when: &[FxHashSet<usize>],
-) -> FxHashSet<usize> {
-    if supports.is_empty() {
-        return FxHashSet::default();
+    fn push(&mut self, parent: usize) {
+        let mut ancestors = vec![parent];
+
+        while let Some(&ancestor) =
+            self.ancestors[ancestors[ancestors.len() - 1]].get(ancestors.len() - 1)
+        {
+            ancestors.push(ancestor);
+        }
+
+        self.parents.push(parent);
+        self.depths.push(self.depths[parent] + 1);
+        self.ancestors.push(ancestors);
     }
 
-    let single = supports.len() == 1;
-    let mut supports = supports.iter();
-    let first_id = *supports.next().unwrap();
-    let mut builder = falls_when[first_id].clone();
+    fn lowest_common_ancestor(&self, mut a: usize, mut b: usize) -> usize {
+        if self.depths[a] < self.depths[b] {
+            std::mem::swap(&mut a, &mut b);
+        }
+
+        let mut difference = self.depths[a] - self.depths[b];
+        let mut level = 0;
+
+        while difference > 0 {
+            if difference & 1 == 1 {
+                a = self.ancestors[a][level];
+            }
+
+            difference >>= 1;
+            level += 1;
+        }
+
+        if a == b {
+            return a;
+        }
 
-    // removing the single supporting brick will cause this brick to fall as well
-    if single {
-        builder.insert(first_id);
-        return builder;
+        // both nodes are at the same depth, so they have the same number of ancestors
+        for level in (0..self.ancestors[a].len()).rev() {
+            if level < self.ancestors[a].len()
+                && self.ancestors[a][level] != self.ancestors[b][level]
+            {
+                a = self.ancestors[a][level];
+                b = self.ancestors[b][level];
+            }
+        }
+
+        self.parents[a]
     }
 
-    // compute intersection of falls_when sets of all supporting bricks
-    for id in supports {
-        let set = &falls_when[*id];
-        builder.retain(|x| set.contains(x));
+    fn subtree_size(&self, id: usize) -> usize {
+        self.sizes[id + 1]
     }
 
-    builder
+    // Ids of the bricks which fall when removing the brick
+    fn dominated(&self, id: usize) -> Vec<usize> {
+        let mut subtree = BitSet::with_capacity(self.parents.len());
+        subtree.insert(id + 1);
+
+        // parents always come before their children
+        for node in id + 2..self.parents.len() {
+            if subtree.contains(self.parents[node]) {
+                subtree.insert(node);
+            }
+        }
+
+        subtree.remove(id + 1);
+        subtree.iter().map(|node| node - 1).collect()
+    }
 }
 
-fn parse_bricks(input: &[&str]) -> Vec<Brick> {
+fn parse_bricks(input: &[&str]) -> anyhow::Result<Vec<Brick>> {
     let mut bricks = input
         .iter()
-        .map(|line| Brick::new(line.as_bytes()))
-        .collect::<Vec<_>>();
+        .enumerate()
+        .map(|(line, input)| Brick::new(line, input))
+        .collect::<anyhow::Result<Vec<_>>>()?;
 
     bricks.sort_unstable_by_key(|brick| brick.z0);
-    bricks
+    Ok(bricks)
 }
 
 struct Brick {
-    // assume *0 will always be less than or equal to *1
+    // index of the brick's line in the input
+    line: usize,
+    // *0 is always less than or equal to *1
     x0: usize,
     y0: usize,
     z0: usize,
@@ -138,31 +243,112 @@ struct Brick {
 }
 
 impl Brick {
-    fn new(input: &[u8]) -> Self {
-        let mut iter = input
-            .split(|char| !char.is_ascii_digit())
-            .map(|value| value.parse_dec());
+    fn new(line: usize, input: &str) -> anyhow::Result<Self> {
+        let values = input
+            .as_bytes()
+            .split(|char| *char == b',' || *char == b'~')
+            .collect::<Vec<_>>();
 
-        Self {
-            x0: iter.next().unwrap(),
-            y0: iter.next().unwrap(),
-            z0: iter.next().unwrap(),
-            x1: iter.next().unwrap(),
-            y1: iter.next().unwrap(),
-            z1: iter.next().unwrap(),
-        }
-    }
-
-    fn indexes(&self) -> impl Iterator<Item = usize> + '_ {
-        // defined as 2 iterators so it gets inlined as 2 loops which reduces branching
-        (self.y0..=self.y1).flat_map(|y| {
-            (0..self.x1 - self.x0 + 1).scan(self.x0 + y * GRID_SIZE, |index, _| {
-                let current = *index;
-                // incrementing a single value is faster than recomputing the index from x and y
-                *index += 1;
-                Some(current)
-            })
-        })
+        let valid = values.len() == 6
+            && values.iter().all(|value| {
+                !value.is_empty() && value.len() < 20 && value.iter().all(u8::is_ascii_digit)
+            });
+
+        if !valid {
+            anyhow::bail!("Invalid brick '{input}', expected it to look like 1,0,1~1,2,1");
+        }
+
+        let brick = Self {
+            line,
+            x0: values[0].parse_dec(),
+            y0: values[1].parse_dec(),
+            z0: values[2].parse_dec(),
+            x1: values[3].parse_dec(),
+            y1: values[4].parse_dec(),
+            z1: values[5].parse_dec(),
+        };
+
+        if brick.x0 > brick.x1 || brick.y0 > brick.y1 || brick.z0 > brick.z1 {
+            anyhow::bail!("Brick '{input}' must start at its lowest coordinates");
+        }
+
+        if brick.z0 == 0 {
+            anyhow::bail!("Brick '{input}' is in the ground, z must be at least 1");
+        }
+
+        let footprint = (brick.x1 - brick.x0 + 1).checked_mul(brick.y1 - brick.y0 + 1);
+
+        if footprint.is_none_or(|footprint| footprint > MAX_FOOTPRINT) {
+            anyhow::bail!(
+                "Brick '{input}' is too large, it can cover at most {MAX_FOOTPRINT} positions"
+            );
+        }
+
+        Ok(brick)
+    }
+
+    fn positions(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (self.y0..=self.y1).flat_map(|y| (self.x0..=self.x1).map(move |x| (x, y)))
+    }
+}
+
+// Top down view of the stack, storing the brick at the top of each position
+enum HeightMap {
+    Dense {
+        points: Vec<Point>,
+        x0: usize,
+        y0: usize,
+        width: usize,
+    },
+    // Used when the bricks are spread out too far for a grid covering all of them
+    Sparse(FxHashMap<(usize, usize), Point>),
+}
+
+impl HeightMap {
+    fn new(bricks: &[Brick]) -> Self {
+        let x0 = bricks.iter().map(|brick| brick.x0).min().unwrap_or(0);
+        let y0 = bricks.iter().map(|brick| brick.y0).min().unwrap_or(0);
+        let x1 = bricks.iter().map(|brick| brick.x1).max().unwrap_or(0);
+        let y1 = bricks.iter().map(|brick| brick.y1).max().unwrap_or(0);
+
+        let width = x1 - x0 + 1;
+        let area = width.checked_mul(y1 - y0 + 1);
+
+        match area {
+            Some(area) if area <= MAX_DENSE_AREA => Self::Dense {
+                points: vec![Point::default(); area],
+                x0,
+                y0,
+                width,
+            },
+            _ => Self::Sparse(FxHashMap::default()),
+        }
+    }
+
+    fn get(&self, x: usize, y: usize) -> Point {
+        match self {
+            Self::Dense {
+                points,
+                x0,
+                y0,
+                width,
+            } => points[(y - y0) * width + x - x0],
+            Self::Sparse(points) => points.get(&(x, y)).copied().unwrap_or_default(),
+        }
+    }
+
+    fn set(&mut self, x: usize, y: usize, point: Point) {
+        match self {
+            Self::Dense {
+                points,
+                x0,
+                y0,
+                width,
+            } => points[(y - *y0) * *width + x - *x0] = point,
+            Self::Sparse(points) => {
+                points.insert((x, y), point);
+            }
+        }
     }
 }
 
@@ -180,3 +366,100 @@ impl Default for Point {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use rustc_hash::FxHashSet;
+
+    const EXAMPLE: &[&str] = &[
+        "1,0,1~1,2,1",
+        "0,0,2~2,0,2",
+        "0,2,3~2,2,3",
+        "0,0,4~0,2,4",
+        "2,0,5~2,2,5",
+        "0,1,6~2,1,6",
+        "1,1,8~1,1,9",
+    ];
+
+    // The bricks which fall when removing each brick, found by intersecting the bricks which make
+    // each of the supports fall (along with the support itself)
+    fn falling_bricks(supports: &[Vec<usize>]) -> Vec<Vec<usize>> {
+        let mut falls_when = Vec::<FxHashSet<usize>>::with_capacity(supports.len());
+
+        for supports in supports {
+            let load_bearing = supports
+                .iter()
+                .map(|&support| {
+                    let mut bricks = falls_when[support].clone();
+                    bricks.insert(support);
+                    bricks
+                })
+                .reduce(|a, b| a.intersection(&b).copied().collect())
+                .unwrap_or_default();
+
+            falls_when.push(load_bearing);
+        }
+
+        (0..supports.len())
+            .map(|id| {
+                (0..supports.len())
+                    .filter(|&other| falls_when[other].contains(&id))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn example() {
+        assert_eq!(part_a(EXAMPLE).unwrap().to_string(), "5");
+        assert_eq!(part_b(EXAMPLE, &Args::default()).unwrap().to_string(), "7");
+        assert_eq!(
+            part_b(EXAMPLE, &Args::parse("remove=0"))
+                .unwrap()
+                .to_string(),
+            "6 (1, 2, 3, 4, 5, 6)"
+        );
+    }
+
+    #[test]
+    fn matches_intersecting_supports() {
+        let mut rng = StdRng::seed_from_u64(22);
+
+        for _ in 0..100 {
+            let input = (0..rng.gen_range(1..200))
+                .map(|_| {
+                    let (x, y, z) = (
+                        rng.gen_range(0..5),
+                        rng.gen_range(0..5),
+                        rng.gen_range(1..50),
+                    );
+                    let length = rng.gen_range(0..3);
+
+                    match rng.gen_range(0..3) {
+                        0 => format!("{x},{y},{z}~{},{y},{z}", (x + length).min(4)),
+                        1 => format!("{x},{y},{z}~{x},{},{z}", (y + length).min(4)),
+                        _ => format!("{x},{y},{z}~{x},{y},{}", z + length),
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            let input = input.iter().map(String::as_str).collect::<Vec<_>>();
+            let supports = settle(&parse_bricks(&input).unwrap());
+            let dominators = Dominators::new(&supports);
+
+            for (id, falling) in falling_bricks(&supports).into_iter().enumerate() {
+                assert_eq!(dominators.subtree_size(id) - 1, falling.len());
+                assert_eq!(dominators.dominated(id), falling);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_huge_bricks() {
+        assert!(Brick::new(0, "0,0,1~0,4000000000,1").is_err());
+        assert!(Brick::new(0, "0,0,1~1023,1023,1").is_ok());
+        assert!(Brick::new(0, "0,0,1~1023,1024,1").is_err());
+    }
+}
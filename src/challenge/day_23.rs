@@ -1,50 +1,308 @@
-use crate::utils::IndexMapBuilder;
+use crate::{
+    args::Args,
+    utils::{BitSet, IndexMapBuilder},
+};
 use rustc_hash::FxHashSet;
-use std::ops::Index;
-
-pub fn part_a(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
-    Ok(solve(input, true))
+use std::{
+    ops::Index,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+// Mazes with at most this many junctions store the visited junctions as bits of a u128
+const MAX_FAST_JUNCTIONS: usize = u128::BITS as usize;
+// Number of branches per thread the search is split into
+const BRANCHES_PER_THREAD: usize = 16;
+
+// Passing `junctions` prints the junctions along the longest hike and `render` prints the maze
+// with the hike marked using O, the same way as the puzzle
+pub fn part_a(input: &[&str], args: &Args) -> anyhow::Result<impl std::fmt::Display> {
+    solve(input, true, args)
 }
 
-pub fn part_b(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
-    Ok(solve(input, false))
+pub fn part_b(input: &[&str], args: &Args) -> anyhow::Result<impl std::fmt::Display> {
+    solve(input, false, args)
 }
 
-fn solve(input: &[&str], slopes: bool) -> usize {
+fn solve(input: &[&str], slopes: bool, args: &Args) -> anyhow::Result<usize> {
     let maze = Maze::new(input, slopes);
-    let graph = build_graph(maze);
-    // Distance starts at 1 because the starting position is skipped
-    find_longest_path(1, 1, &graph, &mut vec![false; graph.len()])
+    let hike = find_longest_hike(&maze)?;
+
+    if args.flag("junctions") {
+        let junctions = hike
+            .junctions
+            .iter()
+            .map(|(x, y)| format!("({x}, {y})"))
+            .collect::<Vec<_>>();
+
+        println!("{}", junctions.join(" -> "));
+    }
+
+    if args.flag("render") {
+        println!("{}", render(&maze, &hike));
+    }
+
+    Ok(hike.length)
 }
 
-// DFS search of the optimized graph of the maze's junctions
-fn find_longest_path(id: usize, distance: usize, graph: &[Node], visited: &mut [bool]) -> usize {
-    if id == 0 {
-        return distance;
+fn find_longest_hike(maze: &Maze) -> anyhow::Result<Hike> {
+    let (graph, positions) = build_graph(maze);
+
+    let search = Search::new(&graph);
+
+    let (length, ids) = if graph.len() <= MAX_FAST_JUNCTIONS {
+        search.run::<u128>()
+    } else {
+        search.run::<BitSet>()
+    };
+
+    // the hike always starts at the top of the maze and always continues down to the first node
+    let mut tiles = Vec::with_capacity(length + 1);
+
+    if !ids.is_empty() {
+        tiles.extend([(1, 0), (1, 1)]);
     }
 
-    visited[id] = true;
-    let mut max = 0;
+    for pair in ids.windows(2) {
+        // when there are multiple edges between the junctions, the longest one was used
+        let edge = graph[pair[0]]
+            .edges()
+            .iter()
+            .filter(|edge| edge.id == pair[1])
+            .max_by_key(|edge| edge.distance)
+            .unwrap();
 
-    for edge in graph[id].edges() {
-        if visited[edge.id] {
-            continue;
+        tiles.extend_from_slice(&edge.tiles);
+    }
+
+    Ok(Hike {
+        length,
+        junctions: ids.iter().map(|&id| positions[id]).collect(),
+        tiles,
+    })
+}
+
+// Draws the maze with every tile of the hike (except for the starting one) marked with an O
+fn render(maze: &Maze, hike: &Hike) -> String {
+    let mut grid = maze
+        .grid
+        .iter()
+        .map(|row| row.as_bytes().to_vec())
+        .collect::<Vec<_>>();
+
+    for &(x, y) in hike.tiles.iter().skip(1) {
+        grid[y][x] = b'O';
+    }
+
+    grid[0][1] = b'S';
+
+    grid.into_iter()
+        .map(|row| String::from_utf8(row).unwrap())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+struct Hike {
+    length: usize,
+    // positions of the junctions along the hike, starting with the first node
+    junctions: Vec<(usize, usize)>,
+    // every tile of the hike, starting with the top of the maze
+    tiles: Vec<(usize, usize)>,
+}
+
+// DFS search of the optimized graph of the maze's junctions
+//  - a branch is pruned once entering each of the remaining junctions using its longest edge can't
+//    beat the longest path found so far
+//  - when a single junction leads to the end of the maze, the path has to end after reaching it,
+//    as it would be impossible to reach the end otherwise
+//  - the first levels of the search are expanded and the branches are split between threads
+struct Search<'a> {
+    graph: &'a [Node],
+    // distance of the longest edge leading to each junction
+    longest_edges: Vec<usize>,
+    // id of the only junction leading to the end of the maze
+    last: Option<usize>,
+    longest_path: AtomicUsize,
+    // length and junction ids of the longest path
+    path: Mutex<(usize, Vec<usize>)>,
+}
+
+impl<'a> Search<'a> {
+    fn new(graph: &'a [Node]) -> Self {
+        let mut longest_edges = vec![0; graph.len()];
+        let mut last = Vec::new();
+
+        for (id, node) in graph.iter().enumerate() {
+            for edge in node.edges() {
+                longest_edges[edge.id] = longest_edges[edge.id].max(edge.distance);
+
+                if edge.id == 0 {
+                    last.push(id);
+                }
+            }
         }
 
-        max = max.max(find_longest_path(
-            edge.id,
-            distance + edge.distance,
+        Self {
             graph,
-            visited,
-        ));
+            longest_edges,
+            last: match last[..] {
+                [last] => Some(last),
+                _ => None,
+            },
+            longest_path: AtomicUsize::new(0),
+            path: Mutex::new((0, Vec::new())),
+        }
     }
 
-    visited[id] = false;
-    max
+    fn run<V: Visited>(self) -> (usize, Vec<usize>) {
+        let start = Branch {
+            id: 1,
+            // Distance starts at 1 because the starting position is skipped
+            distance: 1,
+            visited: V::default().with(1),
+            remaining: self.longest_edges.iter().sum::<usize>() - self.longest_edges[1],
+        };
+
+        let threads = std::thread::available_parallelism().map_or(1, |count| count.get());
+        let branches = self.split(start, threads * BRANCHES_PER_THREAD);
+        let next = AtomicUsize::new(0);
+
+        std::thread::scope(|scope| {
+            for _ in 0..threads {
+                scope.spawn(|| {
+                    while let Some((branch, path)) =
+                        branches.get(next.fetch_add(1, Ordering::Relaxed))
+                    {
+                        self.search(branch, &mut path.clone());
+                    }
+                });
+            }
+        });
+
+        self.path.into_inner().unwrap()
+    }
+
+    // Expands the first levels of the search until there are enough branches,
+    // each of them is paired with the ids of the junctions along it
+    fn split<V: Visited>(&self, start: Branch<V>, count: usize) -> Vec<(Branch<V>, Vec<usize>)> {
+        let mut branches = vec![(start, vec![1])];
+
+        while branches.len() < count && branches.iter().any(|(branch, _)| branch.id != 0) {
+            let mut next = Vec::with_capacity(branches.len() * 3);
+
+            for (branch, path) in branches {
+                if branch.id == 0 {
+                    next.push((branch, path));
+                    continue;
+                }
+
+                next.extend(self.next_branches(&branch).map(|branch| {
+                    let mut path = path.clone();
+                    path.push(branch.id);
+                    (branch, path)
+                }));
+            }
+
+            branches = next;
+        }
+
+        branches
+    }
+
+    // The path contains the ids of the junctions along the branch
+    fn search<V: Visited>(&self, branch: &Branch<V>, path: &mut Vec<usize>) {
+        if branch.id == 0 {
+            let previous = self
+                .longest_path
+                .fetch_max(branch.distance, Ordering::Relaxed);
+
+            if branch.distance > previous {
+                let mut longest = self.path.lock().unwrap();
+
+                // another thread might have found a longer path in the meantime
+                if branch.distance > longest.0 {
+                    *longest = (branch.distance, path.clone());
+                }
+            }
+
+            return;
+        }
+
+        if branch.distance + branch.remaining <= self.longest_path.load(Ordering::Relaxed) {
+            return;
+        }
+
+        for next in self.next_branches(branch) {
+            path.push(next.id);
+            self.search(&next, path);
+            path.pop();
+        }
+    }
+
+    fn next_branches<'b, V: Visited>(
+        &'b self,
+        branch: &'b Branch<V>,
+    ) -> impl Iterator<Item = Branch<V>> + 'b {
+        let last = self.last == Some(branch.id);
+
+        self.graph[branch.id]
+            .edges()
+            .iter()
+            .filter(move |edge| !branch.visited.contains(edge.id) && (!last || edge.id == 0))
+            .map(move |edge| Branch {
+                id: edge.id,
+                distance: branch.distance + edge.distance,
+                visited: branch.visited.with(edge.id),
+                remaining: branch.remaining - self.longest_edges[edge.id],
+            })
+    }
+}
+
+// A partial path through the maze
+struct Branch<V> {
+    // id of the junction at the end of the path
+    id: usize,
+    distance: usize,
+    visited: V,
+    // sum of the longest edges leading to the unvisited junctions
+    remaining: usize,
 }
 
-// Build a graph of the mazes junctions and the edges between them
-fn build_graph(maze: Maze) -> Vec<Node> {
+// The junctions visited by a branch
+trait Visited: Default + Send + Sync {
+    fn contains(&self, id: usize) -> bool;
+
+    // Returns a copy of the set with the junction added
+    fn with(&self, id: usize) -> Self;
+}
+
+impl Visited for u128 {
+    fn contains(&self, id: usize) -> bool {
+        self & (1 << id) != 0
+    }
+
+    fn with(&self, id: usize) -> Self {
+        self | 1 << id
+    }
+}
+
+impl Visited for BitSet {
+    fn contains(&self, id: usize) -> bool {
+        BitSet::contains(self, id)
+    }
+
+    fn with(&self, id: usize) -> Self {
+        let mut visited = self.clone();
+        visited.insert(id);
+        visited
+    }
+}
+
+// Build a graph of the mazes junctions and the edges between them,
+// along with the position of each junction
+fn build_graph(maze: &Maze) -> (Vec<Node>, Vec<(usize, usize)>) {
     let mut builder = IndexMapBuilder::<(usize, usize), Node>::default();
     builder.reserve(maze.end);
 
@@ -54,8 +312,8 @@ fn build_graph(maze: Maze) -> Vec<Node> {
     visited.insert((1, 0));
 
     // skips the starting position (x = 1, y = 0) to avoid requiring edge detection
-    build_graph_node(1, 1, &mut builder, &mut visited, &maze);
-    builder.build()
+    build_graph_node(1, 1, &mut builder, &mut visited, maze);
+    builder.build_with_keys()
 }
 
 // DFS search of the entire maze
@@ -87,6 +345,7 @@ fn build_graph_node(
             prev_y,
             distance,
             traversable,
+            tiles,
         } = match find_next_junction(direction, x, y, start_x, start_y, maze) {
             Some(junction) => junction,
             None => continue,
@@ -98,10 +357,11 @@ fn build_graph_node(
         let end_id = builder.find_index((x, y));
 
         update_path(
-            start_id,
+            (start_id, start_x, start_y),
             end_id,
             distance,
             traversable,
+            tiles,
             builder.values_mut(),
         );
 
@@ -132,6 +392,7 @@ fn find_next_junction(
 ) -> Option<NextJunction> {
     let mut distance = 1;
     let mut traversable = Traversable::Both;
+    let mut tiles = vec![(x, y)];
 
     loop {
         if (x, y) == maze.end {
@@ -166,6 +427,7 @@ fn find_next_junction(
         (prev_x, prev_y) = (x, y);
         (direction, x, y) = next;
         distance += 1;
+        tiles.push((x, y));
     }
 
     Some(NextJunction {
@@ -175,38 +437,53 @@ fn find_next_junction(
         prev_y,
         distance,
         traversable,
+        tiles,
     })
 }
 
+// The tiles lead from the start (excluding it) to the end junction (including it)
 fn update_path(
-    start_id: usize,
+    (start_id, start_x, start_y): (usize, usize, usize),
     end_id: usize,
     distance: usize,
     traversable: Traversable,
+    tiles: Vec<(usize, usize)>,
     graph: &mut [Node],
 ) {
+    // the same tiles in reverse, leading from the end back to the start
+    let reversed = || {
+        let mut reversed = tiles[..tiles.len() - 1].to_vec();
+        reversed.reverse();
+        reversed.push((start_x, start_y));
+        reversed
+    };
+
     // if slopes are slippery the path might not be traversable in both directions
     match traversable {
         Traversable::Forward => {
             graph[start_id].insert_edge(Edge {
                 id: end_id,
                 distance,
+                tiles,
             });
         }
         Traversable::Backward => {
             graph[end_id].insert_edge(Edge {
                 id: start_id,
                 distance,
+                tiles: reversed(),
             });
         }
         Traversable::Both => {
-            graph[start_id].insert_edge(Edge {
-                id: end_id,
-                distance,
-            });
             graph[end_id].insert_edge(Edge {
                 id: start_id,
                 distance,
+                tiles: reversed(),
+            });
+            graph[start_id].insert_edge(Edge {
+                id: end_id,
+                distance,
+                tiles,
             });
         }
     }
@@ -234,6 +511,8 @@ impl Node {
 struct Edge {
     id: usize,
     distance: usize,
+    // tiles along the edge, excluding the junction it starts at
+    tiles: Vec<(usize, usize)>,
 }
 
 struct NextJunction {
@@ -243,6 +522,7 @@ struct NextJunction {
     prev_y: usize,
     distance: usize,
     traversable: Traversable,
+    tiles: Vec<(usize, usize)>,
 }
 
 enum Traversable {
@@ -282,3 +562,44 @@ impl Index<(usize, usize)> for Maze<'_> {
         &self.grid[index.1].as_bytes()[index.0]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &[&str] = &[
+        "#.#####################",
+        "#.......#########...###",
+        "#######.#########.#.###",
+        "###.....#.>.>.###.#.###",
+        "###v#####.#v#.###.#.###",
+        "###.>...#.#.#.....#...#",
+        "###v###.#.#.#########.#",
+        "###...#.#.#.......#...#",
+        "#####.#.#.#######.#.###",
+        "#.....#.#.#.......#...#",
+        "#.#####.#.#.#########v#",
+        "#.#...#...#...###...>.#",
+        "#.#.#v#######v###.###v#",
+        "#...#.>.#...>.>.#.###.#",
+        "#####v#.#.###v#.#.###.#",
+        "#.....#...#...#.#.#...#",
+        "#.#########.###.#.#.###",
+        "#...###...#...#...#.###",
+        "###.###.#.###v#####v###",
+        "#...#...#.#.>.>.#.>.###",
+        "#.###.###.#.###.#.#v###",
+        "#.....###...###...#...#",
+        "#####################.#",
+    ];
+
+    #[test]
+    fn example_hikes_with_both_visited_sets() {
+        for (slopes, expected) in [(true, 94), (false, 154)] {
+            let (graph, _) = build_graph(&Maze::new(EXAMPLE, slopes));
+
+            assert_eq!(Search::new(&graph).run::<u128>().0, expected);
+            assert_eq!(Search::new(&graph).run::<BitSet>().0, expected);
+        }
+    }
+}
@@ -1,60 +1,99 @@
-use crate::utils::Bytes;
+use crate::{
+    args::Args,
+    utils::{linear_algebra, Bytes},
+};
+use num_traits::ToPrimitive;
+use std::ops::RangeInclusive;
 
-pub fn part_a(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
-    const MIN: f64 = 200000000000000f64;
-    const MAX: f64 = 400000000000000f64;
+const TEST_AREA: RangeInclusive<i128> = 200000000000000..=400000000000000;
 
-    let lines = input
-        .iter()
-        .map(|line| {
-            let mut iter = split_line(line);
-            let cx = parse_next_number(&mut iter);
-            let cy = parse_next_number(&mut iter);
-            iter.next(); // skip z
-            let dx = parse_next_number(&mut iter);
-            let dy = parse_next_number(&mut iter);
-
-            let d = dy / dx;
-            let c = cy - d * cx;
+// Passing `min=<value>` and `max=<value>` changes the test area, e.g. to 7 and 27 for the example
+pub fn part_a(input: &[&str], args: &Args) -> anyhow::Result<impl std::fmt::Display> {
+    // the values are scaled by the hailstones' velocities, so they're limited to i64 to fit
+    let min = args.get_or("min", *TEST_AREA.start() as i64)? as i128;
+    let max = args.get_or("max", *TEST_AREA.end() as i64)? as i128;
 
-            (c, d, cx, dx)
-        })
-        .collect::<Vec<_>>();
-
-    let mut count = 0;
+    if min > max {
+        anyhow::bail!("The minimum value {min} is larger than the maximum value {max}");
+    }
 
-    for (i, &(c0, d0, cx0, dx0)) in lines.iter().take(lines.len() - 1).enumerate() {
-        for &(c1, d1, cx1, dx1) in lines.iter().skip(i + 1) {
-            let d = d0 - d1;
+    let hailstones = parse_hailstones(input);
+    Ok(count_intersections(&hailstones, min..=max))
+}
 
-            if d == 0f64 {
-                continue;
-            }
+pub fn part_b(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
+    let hailstones = parse_hailstones(input);
 
-            let x = (c1 - c0) / d;
+    // Some triples of hailstones (e.g. ones with parallel paths) produce a singular system of
+    // equations or a starting position which isn't a whole number, in which case the next triple
+    // of hailstones is used instead
+    for h in hailstones.windows(3) {
+        let matrix = linear_algebra::exact(&build_matrix(h));
 
-            if !(MIN..=MAX).contains(&x)
-                || x.total_cmp(&cx0) != dx0.total_cmp(&0f64)
-                || x.total_cmp(&cx1) != dx1.total_cmp(&0f64)
-            {
-                continue;
-            }
+        let solution = match linear_algebra::solve(matrix) {
+            Ok(solution) => solution,
+            Err(_) => continue,
+        };
 
-            let y = x * d0 + c0;
+        let [cx, cy, cz] = [&solution[0], &solution[2], &solution[4]];
 
-            if (MIN..=MAX).contains(&y) {
-                count += 1;
-            }
+        if !cx.is_integer() || !cy.is_integer() || !cz.is_integer() {
+            continue;
         }
+
+        return (cx + cy + cz)
+            .to_i64()
+            .ok_or_else(|| anyhow::anyhow!("The result is out of range"));
     }
 
-    Ok(count)
+    anyhow::bail!("Could not find 3 hailstones which determine the rock's trajectory")
 }
 
-pub fn part_b(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
-    let mut input = input.iter();
-    let h: [Hailstone; 3] = std::array::from_fn(|_| Hailstone::new(input.next().unwrap()));
+// Counts the pairs of hailstones whose future paths cross inside the area (ignoring z)
+fn count_intersections(hailstones: &[Hailstone], area: RangeInclusive<i128>) -> usize {
+    hailstones
+        .iter()
+        .enumerate()
+        .flat_map(|(i, a)| hailstones[i + 1..].iter().map(move |b| (a, b)))
+        .filter(|(a, b)| intersects_within(a, b, &area))
+        .count()
+}
 
+fn intersects_within(a: &Hailstone, b: &Hailstone, area: &RangeInclusive<i128>) -> bool {
+    // The paths cross when a.c + a.d * t = b.c + b.d * s
+    // Solving using cross products gives t = (o x b.d) / (a.d x b.d) and s = (o x a.d) / (a.d x b.d)
+    // where o = b.c - a.c, so t and s are kept as fractions to avoid rounding errors
+    let mut denominator = a.dx * b.dy - a.dy * b.dx;
+
+    if denominator == 0 {
+        // The paths are parallel
+        return false;
+    }
+
+    let ox = b.cx - a.cx;
+    let oy = b.cy - a.cy;
+    let mut t = ox * b.dy - oy * b.dx;
+    let mut s = ox * a.dy - oy * a.dx;
+
+    if denominator < 0 {
+        denominator = -denominator;
+        t = -t;
+        s = -s;
+    }
+
+    if t <= 0 || s <= 0 {
+        // The paths crossed in the past
+        return false;
+    }
+
+    // Compare the numerators of the intersection's coordinates against the scaled area
+    let x = a.cx * denominator + a.dx * t;
+    let y = a.cy * denominator + a.dy * t;
+    let area = area.start() * denominator..=area.end() * denominator;
+    area.contains(&x) && area.contains(&y)
+}
+
+fn build_matrix(h: &[Hailstone]) -> [[i128; 7]; 6] {
     // The rock and hailstones are represented by 3 equations, one for each dimension
     // The rock is represented by:
     // x_r = dx_r * t + cx_r
@@ -152,14 +191,14 @@ pub fn part_b(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
     //
     // This set of equations can now be be solved (by hand or) using Gaussian elimination
 
-    let mut matrix = [
+    [
         [
             h[1].dy - h[0].dy,
             h[0].cy - h[1].cy,
             h[0].dx - h[1].dx,
             h[1].cx - h[0].cx,
-            0f64,
-            0f64,
+            0,
+            0,
             h[1].cx * h[1].dy - h[0].cx * h[0].dy + h[0].cy * h[0].dx - h[1].cy * h[1].dx,
         ],
         [
@@ -167,15 +206,15 @@ pub fn part_b(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
             h[0].cy - h[2].cy,
             h[0].dx - h[2].dx,
             h[2].cx - h[0].cx,
-            0f64,
-            0f64,
+            0,
+            0,
             h[2].cx * h[2].dy - h[0].cx * h[0].dy + h[0].cy * h[0].dx - h[2].cy * h[2].dx,
         ],
         [
             h[1].dz - h[0].dz,
             h[0].cz - h[1].cz,
-            0f64,
-            0f64,
+            0,
+            0,
             h[0].dx - h[1].dx,
             h[1].cx - h[0].cx,
             h[1].cx * h[1].dz - h[0].cx * h[0].dz + h[0].cz * h[0].dx - h[1].cz * h[1].dx,
@@ -183,15 +222,15 @@ pub fn part_b(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
         [
             h[2].dz - h[0].dz,
             h[0].cz - h[2].cz,
-            0f64,
-            0f64,
+            0,
+            0,
             h[0].dx - h[2].dx,
             h[2].cx - h[0].cx,
             h[2].cx * h[2].dz - h[0].cx * h[0].dz + h[0].cz * h[0].dx - h[2].cz * h[2].dx,
         ],
         [
-            0f64,
-            0f64,
+            0,
+            0,
             h[1].dz - h[0].dz,
             h[0].cz - h[1].cz,
             h[0].dy - h[1].dy,
@@ -199,18 +238,19 @@ pub fn part_b(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
             h[1].cy * h[1].dz - h[0].cy * h[0].dz + h[0].cz * h[0].dy - h[1].cz * h[1].dy,
         ],
         [
-            0f64,
-            0f64,
+            0,
+            0,
             h[2].dz - h[0].dz,
             h[0].cz - h[2].cz,
             h[0].dy - h[2].dy,
             h[2].cy - h[0].cy,
             h[2].cy * h[2].dz - h[0].cy * h[0].dz + h[0].cz * h[0].dy - h[2].cz * h[2].dy,
         ],
-    ];
+    ]
+}
 
-    let solution = gaussian_elimination(&mut matrix);
-    Ok(solution[0] as i64 + solution[2] as i64 + solution[4] as i64)
+fn parse_hailstones(input: &[&str]) -> Vec<Hailstone> {
+    input.iter().map(|line| Hailstone::new(line)).collect()
 }
 
 fn split_line(line: &str) -> impl Iterator<Item = &[u8]> + '_ {
@@ -219,17 +259,17 @@ fn split_line(line: &str) -> impl Iterator<Item = &[u8]> + '_ {
         .filter(|part| !part.is_empty())
 }
 
-fn parse_next_number<'a>(iter: &mut impl Iterator<Item = &'a [u8]>) -> f64 {
-    iter.next().unwrap().parse_signed_dec::<i64>() as f64
+fn parse_next_integer<'a>(iter: &mut impl Iterator<Item = &'a [u8]>) -> i128 {
+    iter.next().unwrap().parse_signed_dec()
 }
 
 struct Hailstone {
-    cx: f64,
-    cy: f64,
-    cz: f64,
-    dx: f64,
-    dy: f64,
-    dz: f64,
+    cx: i128,
+    cy: i128,
+    cz: i128,
+    dx: i128,
+    dy: i128,
+    dz: i128,
 }
 
 impl Hailstone {
@@ -237,41 +277,35 @@ impl Hailstone {
         let mut iter = split_line(line);
 
         Self {
-            cx: parse_next_number(&mut iter),
-            cy: parse_next_number(&mut iter),
-            cz: parse_next_number(&mut iter),
-            dx: parse_next_number(&mut iter),
-            dy: parse_next_number(&mut iter),
-            dz: parse_next_number(&mut iter),
+            cx: parse_next_integer(&mut iter),
+            cy: parse_next_integer(&mut iter),
+            cz: parse_next_integer(&mut iter),
+            dx: parse_next_integer(&mut iter),
+            dy: parse_next_integer(&mut iter),
+            dz: parse_next_integer(&mut iter),
         }
     }
 }
 
-const N: usize = 6;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-fn gaussian_elimination(matrix: &mut [[f64; N + 1]; N]) -> [f64; N] {
-    for i in 0..N {
-        for j in i + 1..N {
-            let ratio = matrix[j][i] / matrix[i][i];
+    const EXAMPLE: &[&str] = &[
+        "19, 13, 30 @ -2,  1, -2",
+        "18, 19, 22 @ -1, -1, -2",
+        "20, 25, 34 @ -2, -2, -4",
+        "12, 31, 28 @ -1, -2, -1",
+        "20, 19, 15 @  1, -5, -3",
+    ];
 
-            for k in 0..N + 1 {
-                matrix[j][k] -= ratio * matrix[i][k];
-            }
-        }
+    #[test]
+    fn example_intersections() {
+        assert_eq!(count_intersections(&parse_hailstones(EXAMPLE), 7..=27), 2);
     }
 
-    let mut solution = [0f64; N];
-    solution[N - 1] = (matrix[N - 1][N] / matrix[N - 1][N - 1]).round();
-
-    for i in (0..N - 1).rev() {
-        solution[i] = matrix[i][N];
-
-        for j in i + 1..N {
-            solution[i] -= matrix[i][j] * solution[j];
-        }
-
-        solution[i] = (solution[i] / matrix[i][i]).round();
+    #[test]
+    fn example_rock() {
+        assert_eq!(part_b(EXAMPLE).unwrap().to_string(), "47");
     }
-
-    solution
 }
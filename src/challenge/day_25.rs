@@ -1,11 +1,16 @@
-use crate::utils::IndexMapBuilder;
+use crate::{args::Args, utils::IndexMapBuilder};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use rustc_hash::FxHashMap;
 use std::{
+    cmp::Ordering,
     collections::{hash_map::Entry, VecDeque},
     hash::Hash,
-    mem::MaybeUninit,
+    str::FromStr,
 };
 
+const CUT_SIZE: usize = 3;
+const MAX_ATTEMPTS: usize = 1000;
+
 // The problem states that the minimum cut contains 3 edges
 // It also implies that the minimum cut splits the graph into 2 similarly sized graphs
 // So the graph is composed of 2 clusters of nodes, connected via 3 edges.
@@ -21,70 +26,145 @@ use std::{
 // It continues searching for 10 more paths until the solution is found
 // This is technically a Monte Carlo algorith - it works well because it's very easy to determine if
 // the solution is correct, so it can simply be repeated until the correct solution is found
-pub fn part_a(input: &[&str]) -> anyhow::Result<impl std::fmt::Display> {
-    let mut graph = build_graph(input);
+//
+// Passing `method=max-flow` uses a deterministic max-flow based search for the minimum cut instead
+// and `seed=<number>` makes the Monte Carlo search reproducible
+// The expected number of edges in the cut can be changed with `cut=<number>` and passing `report`
+// lists the cut edges and the sizes of both components
+pub fn part_a(input: &[&str], args: &Args) -> anyhow::Result<impl std::fmt::Display> {
+    let (mut graph, names) = build_graph(input);
+    let cut_size = args.get_or("cut", CUT_SIZE)?;
+
+    let cut = match args.get_or("method", Method::Random)? {
+        Method::Random => {
+            let rng = match args.get("seed")? {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => StdRng::from_entropy(),
+            };
+
+            find_min_cut_random(&mut graph, cut_size, rng)?
+        }
+        Method::MaxFlow => find_min_cut_max_flow(&graph, cut_size)?,
+    };
+
+    let (first, second) = (cut.size, graph.len() - cut.size);
+    let result = first * second;
+
+    if !args.flag("report") {
+        return Ok(result.to_string());
+    }
+
+    let name = |node: usize| String::from_utf8_lossy(names[node]);
+
+    let edges = cut
+        .edges
+        .iter()
+        .map(|edge| format!("{}/{}", name(edge.from), name(edge.to)))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    Ok(format!(
+        "{result} (cutting {edges} splits the graph into {first} and {second} nodes)"
+    ))
+}
+
+#[derive(Copy, Clone)]
+enum Method {
+    Random,
+    MaxFlow,
+}
+
+impl FromStr for Method {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "random" => Ok(Self::Random),
+            "max-flow" => Ok(Self::MaxFlow),
+            _ => anyhow::bail!("expected random or max-flow"),
+        }
+    }
+}
+
+// The removed edges and the number of nodes in the component containing the first node
+struct Cut {
+    edges: Vec<Edge>,
+    size: usize,
+}
+
+fn find_min_cut_random(
+    graph: &mut [Node],
+    cut_size: usize,
+    mut rng: impl Rng,
+) -> anyhow::Result<Cut> {
     let mut frequencies = Frequencies::default();
 
-    if let Some(result) = find_min_cut(50, &mut graph, &mut frequencies) {
-        return Ok(result);
+    if let Some(cut) = find_min_cut(50, graph, cut_size, &mut frequencies, &mut rng)? {
+        return Ok(cut);
     }
 
-    loop {
-        if let Some(result) = find_min_cut(10, &mut graph, &mut frequencies) {
-            return Ok(result);
+    for _ in 0..MAX_ATTEMPTS {
+        if let Some(cut) = find_min_cut(10, graph, cut_size, &mut frequencies, &mut rng)? {
+            return Ok(cut);
         }
     }
+
+    anyhow::bail!("Could not find a cut of {cut_size} edges after {MAX_ATTEMPTS} attempts")
 }
 
-fn build_graph(input: &[&str]) -> Vec<Node> {
+fn build_graph<'a>(input: &[&'a str]) -> (Vec<Node>, Vec<&'a [u8]>) {
     let mut builder = IndexMapBuilder::<&[u8], Node>::default();
 
     for line in input {
-        let line = line.as_bytes();
-        let from = builder.find_index(&line[..3]);
+        let (from, edges) = line.split_once(": ").unwrap();
+        let from = builder.find_index(from.as_bytes());
 
-        for to in line[5..].split(|char| *char == b' ') {
-            let to = builder.find_index(to);
+        for to in edges.split_ascii_whitespace() {
+            let to = builder.find_index(to.as_bytes());
             builder[from].edges.push(to);
             builder[to].edges.push(from);
         }
     }
 
-    builder.build()
+    builder.build_with_keys()
 }
 
 fn find_min_cut(
     steps: usize,
     graph: &mut [Node],
+    cut_size: usize,
     frequencies: &mut Frequencies<Edge>,
-) -> Option<usize> {
-    let mut rng = rand::thread_rng();
-
+    rng: &mut impl Rng,
+) -> anyhow::Result<Option<Cut>> {
     for _ in 0..steps {
-        let (from, to) = get_node_pair(&mut rng, graph.len());
+        let (from, to) = get_node_pair(rng, graph.len());
 
         for edge in find_path(from, to, graph) {
             frequencies.add(edge);
         }
     }
 
-    for edge in frequencies.most_frequent() {
+    let edges = frequencies.most_frequent(cut_size);
+
+    for edge in &edges {
         graph.get_mut(edge.from).unwrap().remove_edge(edge.to);
         graph.get_mut(edge.to).unwrap().remove_edge(edge.from);
     }
 
-    let size = find_size(0, graph);
+    let visited = find_component(0, graph);
 
-    if size != graph.len() {
-        return Some(size * (graph.len() - size));
-    }
-
-    for edge in frequencies.most_frequent() {
+    for edge in &edges {
         graph.get_mut(edge.from).unwrap().edges.push(edge.to);
         graph.get_mut(edge.to).unwrap().edges.push(edge.from);
     }
 
-    None
+    if visited.iter().all(|visited| *visited) {
+        return Ok(None);
+    }
+
+    // some of the removed edges might not cross the split, so the cut is collected from the
+    // restored graph
+    to_cut(graph, &visited, cut_size).map(Some)
 }
 
 // BFS search of shortest path between from and to
@@ -118,9 +198,218 @@ fn find_path(from: usize, to: usize, graph: &mut [Node]) -> impl Iterator<Item =
     }
 }
 
-// DFS count of accessible nodes
-fn find_size(node: usize, graph: &[Node]) -> usize {
-    let mut size = 0;
+// Computes the max flow (with every edge having a capacity of 1) from the first node to each of
+// the other nodes. The nodes which remain reachable after saturating the flow form one side of
+// the cut separating the node from the source.
+// Each node is merged into the source after its flow is computed, which keeps the augmenting
+// paths short. Merging preserves every cut which doesn't separate the node from the source, so
+// the smallest of the flows is the minimum cut of the whole graph. This means every node has to be
+// checked before knowing that a cut of the requested size is the minimum.
+fn find_min_cut_max_flow(graph: &[Node], cut_size: usize) -> anyhow::Result<Cut> {
+    if graph.len() < 2 {
+        anyhow::bail!("The graph needs at least 2 nodes to be cut");
+    }
+
+    // Visiting the nodes in BFS order means each sink is next to the merged source
+    let order = find_order(0, graph);
+
+    if order.len() != graph.len() {
+        // The graph is already split
+        let mut visited = vec![false; graph.len()];
+        order.iter().for_each(|&node| visited[node] = true);
+        return to_cut(graph, &visited, cut_size);
+    }
+
+    let mut network = FlowNetwork::new(graph);
+    let mut cut = None;
+    network.merge(0);
+
+    for &sink in &order[1..] {
+        // The sink's direct edges to the source already exceed the cut size
+        if network.source_edges(sink) > cut_size {
+            network.merge(sink);
+            continue;
+        }
+
+        let flow = network.max_flow(sink, cut_size + 1);
+
+        match flow.cmp(&cut_size) {
+            // A smaller cut separates the sink from the source, so a cut of the requested size
+            // isn't the minimum cut
+            Ordering::Less => anyhow::bail!(
+                "The graph can already be split by cutting {flow} edges, \
+                fewer than the requested {cut_size}"
+            ),
+            Ordering::Equal if cut.is_none() => {
+                cut = Some(to_cut(graph, &network.visited, cut_size)?);
+            }
+            _ => {}
+        }
+
+        network.merge(sink);
+    }
+
+    match cut {
+        Some(cut) => Ok(cut),
+        None => anyhow::bail!("The graph can't be split by cutting {cut_size} edges"),
+    }
+}
+
+// Collects the edges between the visited and the remaining nodes
+fn to_cut(graph: &[Node], visited: &[bool], cut_size: usize) -> anyhow::Result<Cut> {
+    let edges = graph
+        .iter()
+        .enumerate()
+        .filter(|(from, _)| visited[*from])
+        .flat_map(|(from, node)| node.edges.iter().map(move |&to| (from, to)))
+        .filter(|(_, to)| !visited[*to])
+        .map(|(from, to)| Edge::new(from, to))
+        .collect::<Vec<_>>();
+
+    match edges.len().cmp(&cut_size) {
+        Ordering::Less => anyhow::bail!(
+            "The graph can already be split by cutting {} edges, fewer than the requested {cut_size}",
+            edges.len()
+        ),
+        Ordering::Greater => anyhow::bail!(
+            "The graph is split by {} edges, not {cut_size}",
+            edges.len()
+        ),
+        Ordering::Equal => {}
+    }
+
+    let size = visited.iter().filter(|visited| **visited).count();
+    Ok(Cut { edges, size })
+}
+
+// Residual network where each edge is represented by a pair of opposite arcs (a and a ^ 1)
+struct FlowNetwork {
+    arcs: Vec<Arc>,
+    adjacent: Vec<Vec<usize>>,
+    source: Vec<usize>,
+    merged: Vec<bool>,
+    parents: Vec<Option<usize>>,
+    visited: Vec<bool>,
+}
+
+struct Arc {
+    to: usize,
+    flow: i8,
+}
+
+impl FlowNetwork {
+    fn new(graph: &[Node]) -> Self {
+        let mut arcs = Vec::new();
+        let mut adjacent = vec![Vec::new(); graph.len()];
+
+        for (from, node) in graph.iter().enumerate() {
+            for &to in node.edges.iter().filter(|&&to| from < to) {
+                adjacent[from].push(arcs.len());
+                arcs.push(Arc { to, flow: 0 });
+                adjacent[to].push(arcs.len());
+                arcs.push(Arc { to: from, flow: 0 });
+            }
+        }
+
+        Self {
+            arcs,
+            adjacent,
+            source: Vec::new(),
+            merged: vec![false; graph.len()],
+            parents: vec![None; graph.len()],
+            visited: vec![false; graph.len()],
+        }
+    }
+
+    fn merge(&mut self, node: usize) {
+        self.source.push(node);
+        self.merged[node] = true;
+    }
+
+    // Number of edges between the node and the merged source
+    fn source_edges(&self, node: usize) -> usize {
+        self.adjacent[node]
+            .iter()
+            .filter(|&&arc| self.merged[self.arcs[arc].to])
+            .count()
+    }
+
+    // Edmonds-Karp from the merged source, stopping once the flow reaches the limit
+    fn max_flow(&mut self, sink: usize, limit: usize) -> usize {
+        self.arcs.iter_mut().for_each(|arc| arc.flow = 0);
+        let mut flow = 0;
+
+        while flow < limit && self.find_augmenting_path(sink) {
+            let mut node = sink;
+
+            while let Some(arc) = self.parents[node] {
+                self.arcs[arc].flow += 1;
+                self.arcs[arc ^ 1].flow -= 1;
+                node = self.arcs[arc ^ 1].to;
+            }
+
+            flow += 1;
+        }
+
+        flow
+    }
+
+    // BFS through arcs with remaining capacity, storing the arc used to reach each node
+    // When no path is found, the visited nodes form the source's side of the cut
+    fn find_augmenting_path(&mut self, sink: usize) -> bool {
+        self.parents.fill(None);
+        self.visited.fill(false);
+        let mut queue = VecDeque::with_capacity(self.adjacent.len());
+
+        for &node in &self.source {
+            self.visited[node] = true;
+            queue.push_back(node);
+        }
+
+        while let Some(node) = queue.pop_front() {
+            for &arc in &self.adjacent[node] {
+                let Arc { to, flow } = self.arcs[arc];
+
+                if flow < 1 && !self.visited[to] {
+                    self.visited[to] = true;
+                    self.parents[to] = Some(arc);
+
+                    if to == sink {
+                        return true;
+                    }
+
+                    queue.push_back(to);
+                }
+            }
+        }
+
+        false
+    }
+}
+
+// BFS order of the nodes reachable from the starting node
+fn find_order(node: usize, graph: &[Node]) -> Vec<usize> {
+    let mut visited = vec![false; graph.len()];
+    let mut order = vec![node];
+    visited[node] = true;
+    let mut index = 0;
+
+    while let Some(&node) = order.get(index) {
+        index += 1;
+
+        for &edge in &graph[node].edges {
+            if !visited[edge] {
+                visited[edge] = true;
+                order.push(edge);
+            }
+        }
+    }
+
+    order
+}
+
+// DFS search of accessible nodes
+fn find_component(node: usize, graph: &[Node]) -> Vec<bool> {
     let mut visited = vec![false; graph.len()];
     let mut queue = vec![node];
 
@@ -129,7 +418,6 @@ fn find_size(node: usize, graph: &[Node]) -> usize {
             continue;
         }
 
-        size += 1;
         visited[node] = true;
 
         for &edge in &graph[node].edges {
@@ -139,7 +427,7 @@ fn find_size(node: usize, graph: &[Node]) -> usize {
         }
     }
 
-    size
+    visited
 }
 
 fn get_node_pair(rng: &mut impl rand::Rng, length: usize) -> (usize, usize) {
@@ -209,36 +497,11 @@ impl Iterator for PathIterator<'_> {
 struct Frequencies<T>(FxHashMap<T, usize>);
 
 impl<T: Clone + Eq + PartialEq + Hash> Frequencies<T> {
-    fn most_frequent(&self) -> [&T; 3] {
-        assert!(self.0.len() >= 3);
-
-        let mut top_1_value = MaybeUninit::uninit();
-        let mut top_1_count = 0;
-        let mut top_2_value = MaybeUninit::uninit();
-        let mut top_2_count = 0;
-        let mut top_3_value = MaybeUninit::uninit();
-        let mut top_3_count = 0;
-
-        for (value, &count) in self.0.iter() {
-            if count > top_1_count {
-                (top_3_value, top_3_count) = (top_2_value, top_2_count);
-                (top_2_value, top_2_count) = (top_1_value, top_1_count);
-                (top_1_value, top_1_count) = (MaybeUninit::new(value), count);
-            } else if count > top_2_count {
-                (top_3_value, top_3_count) = (top_2_value, top_2_count);
-                (top_2_value, top_2_count) = (MaybeUninit::new(value), count);
-            } else if count > top_3_count {
-                (top_3_value, top_3_count) = (MaybeUninit::new(value), count);
-            }
-        }
-
-        unsafe {
-            [
-                top_1_value.assume_init(),
-                top_2_value.assume_init(),
-                top_3_value.assume_init(),
-            ]
-        }
+    fn most_frequent(&self, count: usize) -> Vec<T> {
+        let mut values = self.0.iter().collect::<Vec<_>>();
+        values.sort_unstable_by(|a, b| b.1.cmp(a.1));
+        values.truncate(count);
+        values.into_iter().map(|(value, _)| value.clone()).collect()
     }
 
     fn add(&mut self, value: T) {
@@ -252,3 +515,40 @@ impl<T: Clone + Eq + PartialEq + Hash> Frequencies<T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &[&str] = &[
+        "jqt: rhn xhk nvd",
+        "rsh: frs pzl lsr",
+        "xhk: hfx",
+        "cmg: qnr nvd lhk bvb",
+        "rhn: xhk bvb hfx",
+        "bvb: xhk hfx",
+        "pzl: lsr hfx nvd",
+        "qnr: nvd",
+        "ntq: jqt hfx bvb xhk",
+        "nvd: lhk",
+        "lsr: lhk",
+        "rzs: qnr cmg lsr rsh",
+        "frs: qnr lhk lsr",
+    ];
+
+    #[test]
+    fn example_cut() {
+        for method in ["random", "max-flow"] {
+            let args = Args::parse(&format!("method={method} seed=1"));
+            assert_eq!(part_a(EXAMPLE, &args).unwrap().to_string(), "54");
+        }
+    }
+
+    #[test]
+    fn both_methods_only_report_crossing_edges() {
+        for method in ["random", "max-flow"] {
+            let args = Args::parse(&format!("method={method} seed=1 cut=4 report"));
+            assert!(part_a(EXAMPLE, &args).is_err(), "{method}");
+        }
+    }
+}
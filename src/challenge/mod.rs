@@ -1,4 +1,4 @@
-use crate::solution::{build, Challenges};
+use crate::solution::{build, Challenges, WithArgs};
 
 mod day_01;
 mod day_02;
@@ -42,16 +42,16 @@ pub fn challenges() -> Challenges {
         day(12, "Hot Springs", day_12::part_a, day_12::part_b),
         day(13, "Point of Incidence", day_13::part_a, day_13::part_b),
         day(14, "Parabolic Reflector Dish", day_14::part_a, day_14::part_b),
-        day(15, "Lens Library", day_15::part_a, day_15::part_b),
-        day(16, "The Floor Will Be Lava", day_16::part_a, day_16:: part_b),
-        day(17, "Clumsy Crucible", day_17::part_a, day_17::part_b),
-        day(18, "Lavaduct Lagoon", day_18::part_a, day_18::part_b),
-        day(19, "Aplenty", day_19::part_a, day_19::part_b),
-        day(20, "Pulse Propagation", day_20::part_a, day_20::part_b),
-        day(21, "Step Counter", day_21::part_a, day_21::part_b),
-        day(22, "Sand Slabs", day_22::part_a, day_22::part_b),
-        day(23, "A Long Walk", day_23::part_a, day_23::part_b),
-        day(24, "Never Tell Me The Odds", day_24::part_a, day_24::part_b),
-        day(25, "Snowverload", day_25::part_a),
+        day(15, "Lens Library", day_15::part_a, WithArgs(day_15::part_b)),
+        day(16, "The Floor Will Be Lava", WithArgs(day_16::part_a), WithArgs(day_16::part_b)),
+        day(17, "Clumsy Crucible", WithArgs(day_17::part_a), WithArgs(day_17::part_b)),
+        day(18, "Lavaduct Lagoon", WithArgs(day_18::part_a), WithArgs(day_18::part_b)),
+        day(19, "Aplenty", day_19::part_a, WithArgs(day_19::part_b)),
+        day(20, "Pulse Propagation", WithArgs(day_20::part_a), WithArgs(day_20::part_b)),
+        day(21, "Step Counter", WithArgs(day_21::part_a), WithArgs(day_21::part_b)),
+        day(22, "Sand Slabs", day_22::part_a, WithArgs(day_22::part_b)),
+        day(23, "A Long Walk", WithArgs(day_23::part_a), WithArgs(day_23::part_b)),
+        day(24, "Never Tell Me The Odds", WithArgs(day_24::part_a), day_24::part_b),
+        day(25, "Snowverload", WithArgs(day_25::part_a)),
     }
 }
@@ -1,9 +1,11 @@
+use args::Args;
 use challenge::challenges;
 use day::{Day, Part};
 use solution::Challenges;
 use std::io::{BufRead, Write};
 
 mod aoc;
+mod args;
 mod challenge;
 mod day;
 mod solution;
@@ -15,12 +17,40 @@ static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
 
 fn main() -> anyhow::Result<()> {
     if std::env::args().count() > 1 {
-        run(std::env::args().skip(1).map(Ok), false)
+        run(group_args(std::env::args().skip(1)).map(Ok), false)
     } else {
         run(std::io::stdin().lock().lines(), true)
     }
 }
 
+// Arguments which don't start with a day and a part (e.g. `method=max-flow` or `6`) belong to the
+// challenge before them, so `25a method=max-flow` doesn't need to be quoted as a single argument
+fn group_args(args: impl Iterator<Item = String>) -> impl Iterator<Item = String> {
+    let mut specs = Vec::<String>::new();
+
+    for arg in args {
+        match specs.last_mut() {
+            Some(spec) if !starts_challenge(&arg) => {
+                spec.push(' ');
+                spec.push_str(&arg);
+            }
+            _ => specs.push(arg),
+        }
+    }
+
+    specs.into_iter()
+}
+
+// Whether the argument is a day followed by a part, like "25a" or "25 a"
+fn starts_challenge(arg: &str) -> bool {
+    let arg = arg.trim_start();
+    let digits = arg.find(|c: char| !c.is_ascii_digit()).unwrap_or(arg.len());
+    digits > 0
+        && arg[digits..]
+            .trim_start()
+            .starts_with(|c: char| c.is_ascii_alphabetic())
+}
+
 fn run(args: impl Iterator<Item = std::io::Result<String>>, prompt: bool) -> anyhow::Result<()> {
     if prompt {
         print!("> ");
@@ -43,8 +73,8 @@ fn run(args: impl Iterator<Item = std::io::Result<String>>, prompt: bool) -> any
 }
 
 fn solve(arg: &str, challenges: &Challenges) {
-    let (day, part) = match parse_arg(arg) {
-        Ok(Some((day, part))) => (day, part),
+    let (day, part, args) = match parse_arg(arg) {
+        Ok(Some((day, part, args))) => (day, part, args),
         Ok(None) => return,
         Err(err) => {
             println!("Cannot parse '{arg}': {}", err);
@@ -52,7 +82,7 @@ fn solve(arg: &str, challenges: &Challenges) {
         }
     };
 
-    match challenges.solve(day, part) {
+    match challenges.solve(day, part, &args) {
         Ok(solution) => println!(
             "Day {}: {} (Part {}): {} (duration = {:?})",
             solution.day, solution.name, solution.part, solution.result, solution.duration
@@ -61,7 +91,7 @@ fn solve(arg: &str, challenges: &Challenges) {
     }
 }
 
-fn parse_arg(input: &str) -> anyhow::Result<Option<(Day, Part)>> {
+fn parse_arg(input: &str) -> anyhow::Result<Option<(Day, Part, Args)>> {
     let input = input.trim();
 
     if input.is_empty() {
@@ -70,10 +100,15 @@ fn parse_arg(input: &str) -> anyhow::Result<Option<(Day, Part)>> {
 
     let index = input.find(|c: char| !c.is_ascii_digit());
 
-    let (day, part) = match index {
-        Some(index) => (&input[..index], &input[index..]),
+    let (day, rest) = match index {
+        Some(index) => (&input[..index], input[index..].trim_start()),
         None => (input, ""),
     };
 
-    Ok(Some((day.parse()?, part.trim().parse()?)))
+    let (part, args) = match rest.find(char::is_whitespace) {
+        Some(index) => (&rest[..index], &rest[index..]),
+        None => (rest, ""),
+    };
+
+    Ok(Some((day.parse()?, part.parse()?, Args::parse(args))))
 }
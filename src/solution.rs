@@ -1,5 +1,6 @@
 use crate::{
     aoc,
+    args::Args,
     day::{Day, Part},
 };
 use std::time::{Duration, Instant};
@@ -19,6 +20,7 @@ pub trait Challenge {
         part: Part,
         name: &'static str,
         input: &'static [&'static str],
+        args: &Args,
     ) -> anyhow::Result<Solution>;
 }
 
@@ -33,9 +35,30 @@ where
         part: Part,
         name: &'static str,
         input: &'static [&'static str],
+        _: &Args,
+    ) -> anyhow::Result<Solution> {
+        WithArgs(|input, _: &Args| self(input)).solve(day, part, name, input, &Args::default())
+    }
+}
+
+/// Wraps a challenge which accepts extra arguments from the command line
+pub struct WithArgs<F>(pub F);
+
+impl<F, R> Challenge for WithArgs<F>
+where
+    F: Fn(&'static [&'static str], &Args) -> anyhow::Result<R>,
+    R: std::fmt::Display,
+{
+    fn solve(
+        &self,
+        day: Day,
+        part: Part,
+        name: &'static str,
+        input: &'static [&'static str],
+        args: &Args,
     ) -> anyhow::Result<Solution> {
         let start = Instant::now();
-        let output = self(input)?;
+        let output = self.0(input, args)?;
         let duration = start.elapsed();
         let result = output.to_string();
 
@@ -52,7 +75,7 @@ where
 pub struct Challenges([Option<Parts>; 25]);
 
 impl Challenges {
-    pub fn solve(&self, day: Day, part: Part) -> anyhow::Result<Solution> {
+    pub fn solve(&self, day: Day, part: Part, args: &Args) -> anyhow::Result<Solution> {
         let parts = match self.0[day.into_index()] {
             Some(ref parts) => parts,
             None => anyhow::bail!("Day is not defined"),
@@ -68,7 +91,7 @@ impl Challenges {
             None => anyhow::bail!("Part is not solved"),
         };
 
-        challenge.solve(day, part, parts.name, aoc::get(day)?)
+        challenge.solve(day, part, parts.name, aoc::get(day)?, args)
     }
 
     #[doc(hidden)]
@@ -0,0 +1,281 @@
+use std::{
+    hash::{Hash, Hasher},
+    ops::{BitAndAssign, BitOrAssign, SubAssign},
+};
+
+const WORD_BITS: usize = u64::BITS as usize;
+
+/// A growable set of small integers backed by a vector of bit words
+#[derive(Clone, Default, Debug)]
+pub struct BitSet {
+    words: Vec<u64>,
+}
+
+impl BitSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a set which can hold values below `capacity` without reallocating
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            words: vec![0; capacity.div_ceil(WORD_BITS)],
+        }
+    }
+
+    /// Returns the number of values in the set
+    pub fn len(&self) -> usize {
+        self.words
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|word| *word == 0)
+    }
+
+    pub fn contains(&self, value: usize) -> bool {
+        match self.words.get(value / WORD_BITS) {
+            Some(word) => word & (1 << (value % WORD_BITS)) != 0,
+            None => false,
+        }
+    }
+
+    /// Adds the value to the set, returning `true` if it wasn't present before
+    pub fn insert(&mut self, value: usize) -> bool {
+        let index = value / WORD_BITS;
+
+        if index >= self.words.len() {
+            self.words.resize(index + 1, 0);
+        }
+
+        let mask = 1 << (value % WORD_BITS);
+        let inserted = self.words[index] & mask == 0;
+        self.words[index] |= mask;
+        inserted
+    }
+
+    /// Removes the value from the set, returning `true` if it was present before
+    pub fn remove(&mut self, value: usize) -> bool {
+        let Some(word) = self.words.get_mut(value / WORD_BITS) else {
+            return false;
+        };
+
+        let mask = 1 << (value % WORD_BITS);
+        let removed = *word & mask != 0;
+        *word &= !mask;
+        removed
+    }
+
+    pub fn clear(&mut self) {
+        self.words.fill(0);
+    }
+
+    pub fn is_subset(&self, other: &Self) -> bool {
+        self.words
+            .iter()
+            .enumerate()
+            .all(|(i, word)| word & !other.words.get(i).unwrap_or(&0) == 0)
+    }
+
+    pub fn is_disjoint(&self, other: &Self) -> bool {
+        self.words.iter().zip(&other.words).all(|(a, b)| a & b == 0)
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            words: &self.words,
+            index: 0,
+            current: self.words.first().copied().unwrap_or(0),
+        }
+    }
+
+    // Words without the trailing empty ones, so sets with different capacities compare equal
+    fn trimmed(&self) -> &[u64] {
+        let len = self
+            .words
+            .iter()
+            .rposition(|word| *word != 0)
+            .map_or(0, |i| i + 1);
+        &self.words[..len]
+    }
+}
+
+impl PartialEq for BitSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.trimmed() == other.trimmed()
+    }
+}
+
+impl Eq for BitSet {}
+
+impl Hash for BitSet {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.trimmed().hash(state)
+    }
+}
+
+impl BitOrAssign<&BitSet> for BitSet {
+    fn bitor_assign(&mut self, rhs: &BitSet) {
+        if self.words.len() < rhs.words.len() {
+            self.words.resize(rhs.words.len(), 0);
+        }
+
+        for (a, b) in self.words.iter_mut().zip(&rhs.words) {
+            *a |= b;
+        }
+    }
+}
+
+impl BitAndAssign<&BitSet> for BitSet {
+    fn bitand_assign(&mut self, rhs: &BitSet) {
+        for (i, word) in self.words.iter_mut().enumerate() {
+            *word &= rhs.words.get(i).unwrap_or(&0);
+        }
+    }
+}
+
+impl SubAssign<&BitSet> for BitSet {
+    fn sub_assign(&mut self, rhs: &BitSet) {
+        for (a, b) in self.words.iter_mut().zip(&rhs.words) {
+            *a &= !b;
+        }
+    }
+}
+
+impl FromIterator<usize> for BitSet {
+    fn from_iter<T: IntoIterator<Item = usize>>(iter: T) -> Self {
+        let mut set = Self::default();
+        set.extend(iter);
+        set
+    }
+}
+
+impl Extend<usize> for BitSet {
+    fn extend<T: IntoIterator<Item = usize>>(&mut self, iter: T) {
+        for value in iter {
+            self.insert(value);
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a BitSet {
+    type Item = usize;
+
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct Iter<'a> {
+    words: &'a [u64],
+    index: usize,
+    current: u64,
+}
+
+impl Iterator for Iter<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.current == 0 {
+            self.index += 1;
+            self.current = *self.words.get(self.index)?;
+        }
+
+        let lowest_bit = self.current & self.current.wrapping_neg();
+        let item = self.index * WORD_BITS + lowest_bit.trailing_zeros() as usize;
+        self.current ^= lowest_bit;
+
+        Some(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::DefaultHasher;
+
+    fn hash(set: &BitSet) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        set.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn insert_and_remove() {
+        let mut set = BitSet::new();
+        assert!(set.is_empty());
+
+        assert!(set.insert(3));
+        assert!(!set.insert(3));
+        // values past the capacity grow the set
+        assert!(set.insert(200));
+
+        assert!(set.contains(3));
+        assert!(set.contains(200));
+        assert!(!set.contains(4));
+        assert!(!set.contains(10000));
+        assert_eq!(set.len(), 2);
+
+        assert!(set.remove(3));
+        assert!(!set.remove(3));
+        assert!(!set.remove(10000));
+        assert_eq!(set.len(), 1);
+
+        set.clear();
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    fn iterates_in_order_across_words() {
+        let values = [0, 1, 63, 64, 65, 127, 128, 1000];
+        let set = values.iter().rev().copied().collect::<BitSet>();
+        assert_eq!(set.iter().collect::<Vec<_>>(), values);
+        assert_eq!(BitSet::with_capacity(256).iter().next(), None);
+    }
+
+    #[test]
+    fn equality_ignores_capacity() {
+        let mut a = BitSet::with_capacity(1000);
+        let mut b = BitSet::new();
+        assert_eq!(a, b);
+
+        a.insert(5);
+        b.insert(5);
+        b.insert(900);
+        b.remove(900);
+
+        assert_eq!(a, b);
+        assert_eq!(hash(&a), hash(&b));
+
+        b.insert(6);
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn set_operations() {
+        let a = [1, 2, 70, 150].into_iter().collect::<BitSet>();
+        let b = [2, 70, 300].into_iter().collect::<BitSet>();
+
+        let mut union = a.clone();
+        union |= &b;
+        assert_eq!(union.iter().collect::<Vec<_>>(), [1, 2, 70, 150, 300]);
+
+        let mut intersection = a.clone();
+        intersection &= &b;
+        assert_eq!(intersection.iter().collect::<Vec<_>>(), [2, 70]);
+
+        let mut difference = a.clone();
+        difference -= &b;
+        assert_eq!(difference.iter().collect::<Vec<_>>(), [1, 150]);
+
+        assert!(intersection.is_subset(&a));
+        assert!(intersection.is_subset(&b));
+        assert!(!a.is_subset(&b));
+        assert!(difference.is_disjoint(&b));
+        assert!(!a.is_disjoint(&b));
+    }
+}
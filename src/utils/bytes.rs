@@ -13,6 +13,7 @@ pub trait Bytes<'a> {
     where
         T: Copy + Add<T, Output = T> + Shl<T, Output = T> + From<u8>;
 
+    #[allow(dead_code)]
     fn parse_signed_hex<T>(self) -> T
     where
         T: Copy + Add<T, Output = T> + Shl<T, Output = T> + Neg<Output = T> + From<u8>;
@@ -0,0 +1,273 @@
+use rustc_hash::FxHashMap;
+use std::hash::Hash;
+
+/// Describes a sequence of states which starts repeating after `prefix` steps,
+/// with the same `period` states repeating from then on.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Cycle {
+    pub prefix: usize,
+    pub period: usize,
+}
+
+impl Cycle {
+    /// Maps step `n` to the earliest step which results in the same state
+    pub fn reduce(&self, n: usize) -> usize {
+        if n < self.prefix {
+            n
+        } else {
+            self.prefix + (n - self.prefix) % self.period
+        }
+    }
+}
+
+/// Detects a cycle using Brent's algorithm, which only keeps two states in memory.
+/// Never returns if the states don't eventually repeat.
+#[allow(dead_code)]
+pub fn brent<S: Eq + Clone>(initial: &S, mut step: impl FnMut(&mut S)) -> Cycle {
+    let mut power = 1;
+    let mut period = 1;
+    let mut tortoise = initial.clone();
+    let mut hare = initial.clone();
+    step(&mut hare);
+
+    // Find the period by teleporting the tortoise to the hare at every power of two
+    while tortoise != hare {
+        if power == period {
+            tortoise = hare.clone();
+            power *= 2;
+            period = 0;
+        }
+
+        step(&mut hare);
+        period += 1;
+    }
+
+    // With the hare a period ahead, both meet at the start of the cycle
+    let mut tortoise = initial.clone();
+    let mut hare = initial.clone();
+
+    for _ in 0..period {
+        step(&mut hare);
+    }
+
+    let prefix = find_meeting_point(&mut tortoise, &mut hare, &mut step);
+    Cycle { prefix, period }
+}
+
+/// Detects a cycle using Floyd's algorithm, which only keeps two states in memory.
+/// Never returns if the states don't eventually repeat.
+#[allow(dead_code)]
+pub fn floyd<S: Eq + Clone>(initial: &S, mut step: impl FnMut(&mut S)) -> Cycle {
+    let mut tortoise = initial.clone();
+    step(&mut tortoise);
+    let mut hare = tortoise.clone();
+    step(&mut hare);
+
+    // The hare moves twice as fast, so they meet somewhere within the cycle
+    while tortoise != hare {
+        step(&mut tortoise);
+        step(&mut hare);
+        step(&mut hare);
+    }
+
+    let mut tortoise = initial.clone();
+    let prefix = find_meeting_point(&mut tortoise, &mut hare, &mut step);
+
+    let mut period = 1;
+    step(&mut hare);
+
+    while tortoise != hare {
+        step(&mut hare);
+        period += 1;
+    }
+
+    Cycle { prefix, period }
+}
+
+/// Detects a cycle by remembering every state seen so far, returning the states up to and
+/// including the last state of the first period. Never returns if the states don't repeat.
+#[allow(dead_code)]
+pub fn detect<S: Hash + Eq + Clone>(initial: S, step: impl FnMut(&mut S)) -> (Cycle, Vec<S>) {
+    match simulate(initial, usize::MAX, step) {
+        (Some(cycle), states) => (cycle, states),
+        (None, _) => unreachable!(),
+    }
+}
+
+/// Returns the state after `n` steps, skipping over repeating states once a cycle is found
+pub fn state_after<S: Hash + Eq + Clone>(initial: S, n: usize, step: impl FnMut(&mut S)) -> S {
+    let (cycle, mut states) = simulate(initial, n, step);
+
+    let index = match cycle {
+        Some(cycle) => cycle.reduce(n),
+        None => n,
+    };
+
+    states.swap_remove(index)
+}
+
+// Steps through the states until one repeats or `n` steps are taken
+fn simulate<S: Hash + Eq + Clone>(
+    initial: S,
+    n: usize,
+    mut step: impl FnMut(&mut S),
+) -> (Option<Cycle>, Vec<S>) {
+    let mut seen = FxHashMap::default();
+    let mut states = vec![initial.clone()];
+    let mut state = initial;
+    seen.insert(state.clone(), 0);
+
+    for index in 1..=n {
+        step(&mut state);
+
+        if let Some(&prefix) = seen.get(&state) {
+            let period = index - prefix;
+            return (Some(Cycle { prefix, period }), states);
+        }
+
+        seen.insert(state.clone(), index);
+        states.push(state.clone());
+    }
+
+    (None, states)
+}
+
+// Steps both states until they're equal, returning the number of steps taken
+fn find_meeting_point<S: Eq>(
+    tortoise: &mut S,
+    hare: &mut S,
+    step: &mut impl FnMut(&mut S),
+) -> usize {
+    let mut steps = 0;
+
+    while tortoise != hare {
+        step(tortoise);
+        step(hare);
+        steps += 1;
+    }
+
+    steps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 5, 6, ...
+    fn step(value: &mut usize) {
+        *value = if *value == 9 { 5 } else { *value + 1 };
+    }
+
+    #[test]
+    fn reduce() {
+        let cycle = Cycle {
+            prefix: 5,
+            period: 5,
+        };
+
+        assert_eq!(cycle.reduce(3), 3);
+        assert_eq!(cycle.reduce(5), 5);
+        assert_eq!(cycle.reduce(10), 5);
+        assert_eq!(cycle.reduce(1_000_000_003), 8);
+    }
+
+    #[test]
+    fn finds_cycle_after_prefix() {
+        assert_eq!(
+            simulate(0, usize::MAX, step).0,
+            Some(Cycle {
+                prefix: 5,
+                period: 5
+            })
+        );
+        assert_eq!(state_after(0, 3, step), 3);
+        assert_eq!(state_after(0, 10, step), 5);
+        assert_eq!(state_after(0, 1_000_000_003, step), 8);
+    }
+
+    #[test]
+    fn finds_cycle_starting_at_first_state() {
+        let step = |value: &mut usize| *value = (*value + 1) % 3;
+
+        assert_eq!(
+            simulate(0, usize::MAX, step).0,
+            Some(Cycle {
+                prefix: 0,
+                period: 3
+            })
+        );
+        assert_eq!(state_after(0, 1_000_000_000, step), 1);
+    }
+
+    #[test]
+    fn finds_period_of_one() {
+        let step = |value: &mut usize| *value = (*value + 1).min(4);
+
+        assert_eq!(
+            simulate(0, usize::MAX, step).0,
+            Some(Cycle {
+                prefix: 4,
+                period: 1
+            })
+        );
+        assert_eq!(state_after(0, 1_000_000_000, step), 4);
+
+        let (cycle, states) = simulate(7, usize::MAX, |_| {});
+        assert_eq!(
+            cycle,
+            Some(Cycle {
+                prefix: 0,
+                period: 1
+            })
+        );
+        assert_eq!(states, [7]);
+    }
+
+    #[test]
+    fn detectors_agree() {
+        let steps: [fn(&mut usize); 4] = [
+            step,
+            |value| *value = (*value + 1) % 3,
+            |value| *value = (*value + 1).min(4),
+            // pseudo-random values, which repeat after a longer prefix
+            |value| *value = (*value * *value + 1) % 97,
+        ];
+
+        for step in steps {
+            let (cycle, states) = detect(0, step);
+
+            assert_eq!(brent(&0, step), cycle);
+            assert_eq!(floyd(&0, step), cycle);
+            assert_eq!(states.len(), cycle.prefix + cycle.period);
+        }
+
+        let expected = Cycle {
+            prefix: 5,
+            period: 5,
+        };
+
+        assert_eq!(brent(&0, step), expected);
+        assert_eq!(floyd(&0, step), expected);
+        assert_eq!(detect(0, step).1, [0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(
+            brent(&7, |_: &mut usize| {}),
+            Cycle {
+                prefix: 0,
+                period: 1
+            }
+        );
+        assert_eq!(
+            floyd(&7, |_: &mut usize| {}),
+            Cycle {
+                prefix: 0,
+                period: 1
+            }
+        );
+    }
+
+    #[test]
+    fn stops_before_repeating() {
+        assert_eq!(simulate(0, 4, step), (None, vec![0, 1, 2, 3, 4]));
+        assert_eq!(state_after(0, 0, step), 0);
+    }
+}
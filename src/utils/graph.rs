@@ -0,0 +1,136 @@
+use super::BitSet;
+
+/// Finds the strongly connected components of a graph with `count` nodes, where `children`
+/// returns the nodes each node has edges to (Tarjan's algorithm, without recursion).
+/// Components are returned in reverse topological order, so every component comes after all the
+/// components it can reach.
+pub fn strongly_connected_components<I>(
+    count: usize,
+    mut children: impl FnMut(usize) -> I,
+) -> Vec<Vec<usize>>
+where
+    I: IntoIterator<Item = usize>,
+{
+    let mut index = 0;
+    let mut indices = vec![None; count];
+    let mut low_links = vec![0; count];
+    let mut stack = Vec::new();
+    let mut on_stack = BitSet::with_capacity(count);
+    let mut components = Vec::new();
+    // The nodes being visited (the call stack of the recursive version), with their unvisited
+    // children
+    let mut visiting = Vec::<(usize, I::IntoIter)>::new();
+
+    for root in 0..count {
+        if indices[root].is_some() {
+            continue;
+        }
+
+        let mut next = Some(root);
+
+        loop {
+            if let Some(node) = next.take() {
+                indices[node] = Some(index);
+                low_links[node] = index;
+                index += 1;
+                stack.push(node);
+                on_stack.insert(node);
+                visiting.push((node, children(node).into_iter()));
+            }
+
+            let Some((node, remaining)) = visiting.last_mut() else {
+                break;
+            };
+
+            let node = *node;
+
+            match remaining.next() {
+                Some(child) => match indices[child] {
+                    None => next = Some(child),
+                    Some(index) if on_stack.contains(child) => {
+                        low_links[node] = low_links[node].min(index);
+                    }
+                    Some(_) => {}
+                },
+                None => {
+                    visiting.pop();
+
+                    if let Some(&(parent, _)) = visiting.last() {
+                        low_links[parent] = low_links[parent].min(low_links[node]);
+                    }
+
+                    // The node is the root of a component, everything above it on the stack
+                    // belongs to it
+                    if Some(low_links[node]) == indices[node] {
+                        let mut component = Vec::new();
+
+                        while let Some(member) = stack.pop() {
+                            on_stack.remove(member);
+                            component.push(member);
+
+                            if member == node {
+                                break;
+                            }
+                        }
+
+                        components.push(component);
+                    }
+                }
+            }
+        }
+    }
+
+    components
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn components(edges: &[Vec<usize>]) -> Vec<Vec<usize>> {
+        let mut components =
+            strongly_connected_components(edges.len(), |node| edges[node].iter().copied());
+
+        components.iter_mut().for_each(|component| component.sort());
+        components
+    }
+
+    #[test]
+    fn finds_components_in_reverse_topological_order() {
+        // 0 -> {1, 2} -> 3 -> {4, 5, 6} with 7 unconnected
+        let edges = [
+            vec![1],
+            vec![2],
+            vec![1, 3],
+            vec![4],
+            vec![5],
+            vec![6],
+            vec![4],
+            vec![],
+        ];
+
+        assert_eq!(
+            components(&edges),
+            [vec![4, 5, 6], vec![3], vec![1, 2], vec![0], vec![7]]
+        );
+    }
+
+    #[test]
+    fn handles_self_loops_and_empty_graphs() {
+        assert_eq!(components(&[vec![0], vec![0]]), [vec![0], vec![1]]);
+        assert!(components(&[]).is_empty());
+    }
+
+    #[test]
+    fn handles_long_paths_without_recursion() {
+        // a single cycle going through every node
+        let count = 100000;
+        let edges = (0..count)
+            .map(|node| vec![(node + 1) % count])
+            .collect::<Vec<_>>();
+
+        let components = components(&edges);
+        assert_eq!(components.len(), 1);
+        assert_eq!(components[0].len(), count);
+    }
+}
@@ -1,5 +1,6 @@
 use rustc_hash::FxHashMap;
 use std::{
+    borrow::Borrow,
     collections::hash_map::Entry,
     hash::Hash,
     ops::{Index, IndexMut},
@@ -7,6 +8,7 @@ use std::{
 
 pub struct IndexMapBuilder<K, V> {
     index_lookup: FxHashMap<K, usize>,
+    keys: Vec<K>,
     values: Vec<V>,
 }
 
@@ -17,7 +19,16 @@ impl<K, V> IndexMapBuilder<K, V> {
     }
 
     pub fn len(&self) -> usize {
-        self.index_lookup.len()
+        self.keys.len()
+    }
+
+    /// Reverse lookup of the key which was assigned the index
+    pub fn key(&self, index: usize) -> Option<&K> {
+        self.keys.get(index)
+    }
+
+    pub fn keys(&self) -> &[K] {
+        &self.keys
     }
 
     pub fn values(&self) -> &[V] {
@@ -28,12 +39,25 @@ impl<K, V> IndexMapBuilder<K, V> {
         &mut self.values
     }
 
-    pub fn build(mut self) -> Vec<V> {
-        if self.index_lookup.len() < self.values.len() {
-            self.values.truncate(self.index_lookup.len())
+    pub fn iter(&self) -> impl Iterator<Item = (&K, usize, &V)> + '_ {
+        self.keys
+            .iter()
+            .zip(&self.values)
+            .enumerate()
+            .map(|(index, (key, value))| (key, index, value))
+    }
+
+    pub fn build(self) -> Vec<V> {
+        self.build_with_keys().0
+    }
+
+    /// Builds the values while keeping the keys, the key at each index names the value at it
+    pub fn build_with_keys(mut self) -> (Vec<V>, Vec<K>) {
+        if self.keys.len() < self.values.len() {
+            self.values.truncate(self.keys.len())
         }
 
-        self.values
+        (self.values, self.keys)
     }
 }
 
@@ -42,21 +66,34 @@ impl<K, V: Clone + Default> IndexMapBuilder<K, V> {
     pub fn with_capacity(capacity: usize) -> Self {
         IndexMapBuilder {
             index_lookup: FxHashMap::with_capacity_and_hasher(capacity, Default::default()),
+            keys: Vec::with_capacity(capacity),
             values: vec![V::default(); capacity],
         }
     }
 }
 
+impl<K: Eq + PartialEq + Hash, V> IndexMapBuilder<K, V> {
+    /// Looks up the index of the key without inserting it
+    pub fn get_index<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.index_lookup.get(key).copied()
+    }
+}
+
 #[allow(dead_code)]
-impl<K: Eq + PartialEq + Hash, V: Default> IndexMapBuilder<K, V> {
+impl<K: Clone + Eq + PartialEq + Hash, V: Default> IndexMapBuilder<K, V> {
     pub fn find_index(&mut self, key: K) -> usize {
-        let next_index = self.index_lookup.len();
+        let next_index = self.keys.len();
 
         let entry = match self.index_lookup.entry(key) {
             Entry::Occupied(entry) => return *entry.get(),
             Entry::Vacant(entry) => entry,
         };
 
+        self.keys.push(entry.key().clone());
         entry.insert(next_index);
 
         if self.values.len() <= next_index {
@@ -76,6 +113,7 @@ impl<K, V> Default for IndexMapBuilder<K, V> {
     fn default() -> Self {
         Self {
             index_lookup: Default::default(),
+            keys: Default::default(),
             values: Default::default(),
         }
     }
@@ -94,3 +132,60 @@ impl<K, V> IndexMut<usize> for IndexMapBuilder<K, V> {
         &mut self.values[index]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn builder() -> IndexMapBuilder<&'static str, u32> {
+        let mut builder = IndexMapBuilder::with_capacity(4);
+        builder.reserve("a");
+
+        for (key, value) in [("b", 2), ("c", 3), ("a", 1), ("b", 4)] {
+            let index = builder.find_index(key);
+            builder[index] += value;
+        }
+
+        builder
+    }
+
+    #[test]
+    fn lookups() {
+        let builder = builder();
+
+        assert_eq!(builder.len(), 3);
+        assert_eq!(builder.get_index("a"), Some(0));
+        assert_eq!(builder.get_index("c"), Some(2));
+        assert_eq!(builder.get_index("d"), None);
+        assert_eq!(builder.len(), 3);
+
+        assert_eq!(builder.key(1), Some(&"b"));
+        assert_eq!(builder.key(3), None);
+        assert_eq!(builder.keys(), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn iter() {
+        let entries = builder()
+            .iter()
+            .map(|(key, index, value)| (*key, index, *value))
+            .collect::<Vec<_>>();
+
+        assert_eq!(entries, [("a", 0, 1), ("b", 1, 6), ("c", 2, 3)]);
+    }
+
+    #[test]
+    fn build_with_keys() {
+        // the values reserved by the capacity which aren't assigned to a key are dropped
+        assert_eq!(
+            builder().build_with_keys(),
+            (vec![1, 6, 3], vec!["a", "b", "c"])
+        );
+        assert_eq!(builder().build(), [1, 6, 3]);
+
+        let mut builder = IndexMapBuilder::<&str, u32>::default();
+        builder.reserve("a");
+        builder.reserve("b");
+        assert_eq!(builder.build_with_keys(), (vec![0, 0], vec!["a", "b"]));
+    }
+}
@@ -0,0 +1,179 @@
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{Signed, Zero};
+use std::ops::{Div, Mul, Sub};
+
+/// A value which can be used to solve systems of linear equations.
+/// Implemented for `f64` (approximate) and `BigRational` (exact).
+pub trait Scalar:
+    Clone
+    + PartialOrd
+    + Signed
+    + for<'a> Mul<&'a Self, Output = Self>
+    + for<'a> Sub<&'a Self, Output = Self>
+    + for<'a> Div<&'a Self, Output = Self>
+{
+    /// Whether the value is too close to zero to be used as a pivot
+    fn is_negligible(&self) -> bool;
+}
+
+impl Scalar for f64 {
+    fn is_negligible(&self) -> bool {
+        self.abs() < 1e-9
+    }
+}
+
+impl Scalar for BigRational {
+    fn is_negligible(&self) -> bool {
+        self.is_zero()
+    }
+}
+
+/// Converts an integer matrix into one which can be solved exactly
+pub fn exact<const N: usize>(matrix: &[[i128; N]]) -> Vec<Vec<BigRational>> {
+    matrix
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|value| BigRational::from_integer(BigInt::from(*value)))
+                .collect()
+        })
+        .collect()
+}
+
+/// Solves a system of linear equations using Gaussian elimination with partial pivoting.
+/// Each row contains the coefficients of an equation followed by its right hand side.
+pub fn solve<T: Scalar>(mut matrix: Vec<Vec<T>>) -> anyhow::Result<Vec<T>> {
+    let n = matrix.len();
+
+    if matrix.iter().any(|row| row.len() != n + 1) {
+        anyhow::bail!("Expected {n} equations with {n} unknowns");
+    }
+
+    for i in 0..n {
+        // Use the largest remaining value as the pivot to minimize rounding errors
+        let pivot = (i..n)
+            .max_by(|&a, &b| {
+                matrix[a][i]
+                    .abs()
+                    .partial_cmp(&matrix[b][i].abs())
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .unwrap();
+
+        if matrix[pivot][i].is_negligible() {
+            anyhow::bail!("System of equations is singular");
+        }
+
+        matrix.swap(i, pivot);
+        let (top, bottom) = matrix.split_at_mut(i + 1);
+        let pivot_row = &top[i];
+
+        for row in bottom {
+            if row[i].is_zero() {
+                continue;
+            }
+
+            let ratio = row[i].clone() / &pivot_row[i];
+
+            for (value, pivot_value) in row[i..].iter_mut().zip(&pivot_row[i..]) {
+                *value = value.clone() - &(ratio.clone() * pivot_value);
+            }
+        }
+    }
+
+    let mut solution = Vec::<T>::with_capacity(n);
+
+    // Back substitution, the solution is built in reverse
+    for (i, row) in matrix.iter().enumerate().rev() {
+        let mut value = row[n].clone();
+
+        for (coefficient, known) in row[i + 1..n].iter().zip(solution.iter().rev()) {
+            value = value - &(coefficient.clone() * known);
+        }
+
+        solution.push(value / &row[i]);
+    }
+
+    solution.reverse();
+    Ok(solution)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rational(numerator: i64, denominator: i64) -> BigRational {
+        BigRational::new(BigInt::from(numerator), BigInt::from(denominator))
+    }
+
+    fn assert_close(solution: &[f64], expected: &[f64]) {
+        assert_eq!(solution.len(), expected.len());
+
+        for (value, expected) in solution.iter().zip(expected) {
+            assert!(
+                (value - expected).abs() < 1e-9,
+                "{solution:?} != {expected:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn exact_solutions() {
+        // 2x + y = 5, x - y = 1
+        let solution = solve(exact(&[[2, 1, 5], [1, -1, 1]])).unwrap();
+        assert_eq!(solution, [rational(2, 1), rational(1, 1)]);
+
+        // x + 2y = 1, 3x + 4y = 2
+        let solution = solve(exact(&[[1, 2, 1], [3, 4, 2]])).unwrap();
+        assert_eq!(solution, [rational(0, 1), rational(1, 2)]);
+
+        // x + y + z = 6, 2y + 5z = -4, 2x + 5y - z = 27
+        let solution = solve(exact(&[[1, 1, 1, 6], [0, 2, 5, -4], [2, 5, -1, 27]])).unwrap();
+        assert_eq!(solution, [rational(5, 1), rational(3, 1), rational(-2, 1)]);
+    }
+
+    #[test]
+    fn pivoting() {
+        // the first equation can't be used as the pivot for x
+        let solution = solve(exact(&[[0, 1, 2], [1, 0, 3]])).unwrap();
+        assert_eq!(solution, [rational(3, 1), rational(2, 1)]);
+
+        // without swapping the rows, dividing by 1e-20 loses the value of x
+        let solution = solve(vec![vec![1e-20, 1.0, 1.0], vec![1.0, 1.0, 2.0]]).unwrap();
+        assert_close(&solution, &[1.0, 1.0]);
+    }
+
+    #[test]
+    fn approximate_solutions() {
+        let matrix = vec![
+            vec![1.0, 1.0, 1.0, 6.0],
+            vec![0.0, 2.0, 5.0, -4.0],
+            vec![2.0, 5.0, -1.0, 27.0],
+        ];
+
+        assert_close(&solve(matrix).unwrap(), &[5.0, 3.0, -2.0]);
+        assert_close(&solve(vec![vec![0.5, 0.25]]).unwrap(), &[0.5]);
+    }
+
+    #[test]
+    fn singular_systems() {
+        let error = solve(exact(&[[1, 2, 3], [2, 4, 6]])).unwrap_err();
+        assert_eq!(error.to_string(), "System of equations is singular");
+
+        let error = solve(exact(&[[1, 1, 1, 1], [1, 2, 3, 1], [2, 3, 4, 5]])).unwrap_err();
+        assert_eq!(error.to_string(), "System of equations is singular");
+
+        let matrix = vec![vec![1.0, 2.0, 3.0], vec![1.0 + 1e-12, 2.0, 3.0]];
+        assert!(solve(matrix).is_err());
+    }
+
+    #[test]
+    fn invalid_systems() {
+        let error = solve(exact(&[[1, 2, 3]])).unwrap_err();
+        assert_eq!(error.to_string(), "Expected 1 equations with 1 unknowns");
+
+        let error = solve(vec![vec![1.0, 2.0], vec![3.0, 4.0]]).unwrap_err();
+        assert_eq!(error.to_string(), "Expected 2 equations with 2 unknowns");
+    }
+}
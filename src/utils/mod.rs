@@ -1,5 +1,12 @@
+mod bit_set;
 mod bytes;
+pub mod cycle;
+pub mod graph;
 mod index_map;
+pub mod linear_algebra;
+mod ranges;
 
+pub use bit_set::BitSet;
 pub use bytes::Bytes;
 pub use index_map::IndexMapBuilder;
+pub use ranges::RangeSet;
@@ -0,0 +1,294 @@
+use std::ops::Range;
+
+/// A set of values stored as sorted, disjoint and non-adjacent half-open ranges.
+///
+/// Half-open ranges over `u64` avoid the overflow issues of inclusive `u32` ends.
+#[derive(Clone, Default, Eq, PartialEq, Debug)]
+pub struct RangeSet(Vec<(u64, u64)>);
+
+impl RangeSet {
+    /// Returns the number of values in the set
+    pub fn len(&self) -> u64 {
+        self.0.iter().map(|(start, end)| end - start).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn min(&self) -> Option<u64> {
+        self.0.first().map(|(start, _)| *start)
+    }
+
+    pub fn ranges(&self) -> impl Iterator<Item = Range<u64>> + '_ {
+        self.0.iter().map(|(start, end)| *start..*end)
+    }
+
+    #[allow(dead_code)]
+    pub fn union(&self, other: &Self) -> Self {
+        let mut ranges = Vec::with_capacity(self.0.len() + other.0.len());
+        ranges.extend_from_slice(&self.0);
+        ranges.extend_from_slice(&other.0);
+        Self(normalize(ranges))
+    }
+
+    pub fn intersection(&self, other: &Self) -> Self {
+        let mut ranges = Vec::new();
+        let (mut i, mut j) = (0, 0);
+
+        while i < self.0.len() && j < other.0.len() {
+            let (a_start, a_end) = self.0[i];
+            let (b_start, b_end) = other.0[j];
+            let start = a_start.max(b_start);
+            let end = a_end.min(b_end);
+
+            if start < end {
+                ranges.push((start, end));
+            }
+
+            // Advance whichever range ends first, the other one might still overlap the next
+            if a_end < b_end {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+
+        Self(ranges)
+    }
+
+    pub fn difference(&self, other: &Self) -> Self {
+        let mut ranges = Vec::new();
+        let mut j = 0;
+
+        for &(start, end) in &self.0 {
+            let mut start = start;
+
+            // Skip over ranges which end before the current one begins
+            while j < other.0.len() && other.0[j].1 <= start {
+                j += 1;
+            }
+
+            // Carve out every overlapping range, without consuming them as they might overlap
+            // the next range as well
+            for &(cut_start, cut_end) in other.0[j..].iter().take_while(|(s, _)| *s < end) {
+                if start < cut_start {
+                    ranges.push((start, cut_start));
+                }
+
+                start = start.max(cut_end);
+            }
+
+            if start < end {
+                ranges.push((start, end));
+            }
+        }
+
+        Self(ranges)
+    }
+
+    /// Splits the set into values below `point` and values greater than or equal to `point`
+    pub fn split_at(&self, point: u64) -> (Self, Self) {
+        let index = self.0.partition_point(|(_, end)| *end <= point);
+        let mut below = self.0[..index].to_vec();
+        let mut above = self.0[index..].to_vec();
+
+        if let Some(first) = above.first_mut() {
+            if first.0 < point {
+                below.push((first.0, point));
+                first.0 = point;
+            }
+        }
+
+        (Self(below), Self(above))
+    }
+
+    /// Shifts every value contained in a mapping's source range by the mapping's offset.
+    /// Each value is mapped by the first matching mapping, unmatched values remain unchanged.
+    pub fn map_offsets<I>(&self, mappings: I) -> Self
+    where
+        I: IntoIterator<Item = (Range<u64>, i64)>,
+    {
+        let mut unmapped = self.clone();
+        let mut ranges = Vec::new();
+
+        for (source, offset) in mappings {
+            if unmapped.is_empty() {
+                break;
+            }
+
+            let source = Self::from(source);
+
+            ranges.extend(
+                unmapped
+                    .intersection(&source)
+                    .0
+                    .into_iter()
+                    .map(|(start, end)| (shift(start, offset), shift(end, offset))),
+            );
+
+            unmapped = unmapped.difference(&source);
+        }
+
+        ranges.extend(unmapped.0);
+        Self(normalize(ranges))
+    }
+}
+
+impl From<Range<u64>> for RangeSet {
+    fn from(range: Range<u64>) -> Self {
+        if range.start < range.end {
+            Self(vec![(range.start, range.end)])
+        } else {
+            Self::default()
+        }
+    }
+}
+
+impl FromIterator<Range<u64>> for RangeSet {
+    fn from_iter<T: IntoIterator<Item = Range<u64>>>(iter: T) -> Self {
+        Self(normalize(
+            iter.into_iter()
+                .map(|range| (range.start, range.end))
+                .collect(),
+        ))
+    }
+}
+
+fn shift(value: u64, offset: i64) -> u64 {
+    value
+        .checked_add_signed(offset)
+        .expect("range was shifted out of bounds")
+}
+
+// Sorts the ranges and merges overlapping or adjacent ones, dropping empty ranges
+fn normalize(mut ranges: Vec<(u64, u64)>) -> Vec<(u64, u64)> {
+    ranges.retain(|(start, end)| start < end);
+    ranges.sort_unstable();
+
+    let mut merged = Vec::<(u64, u64)>::with_capacity(ranges.len());
+
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(ranges: &[Range<u64>]) -> RangeSet {
+        ranges.iter().cloned().collect()
+    }
+
+    fn ranges(set: &RangeSet) -> Vec<Range<u64>> {
+        set.ranges().collect()
+    }
+
+    #[test]
+    fn merges_overlapping_and_adjacent_ranges() {
+        let set = set(&[8..10, 0..3, 2..5, 5..6, 12..12, 9..11]);
+        assert_eq!(ranges(&set), [0..6, 8..11]);
+        assert_eq!(set.len(), 9);
+        assert_eq!(set.min(), Some(0));
+    }
+
+    #[test]
+    fn ignores_empty_ranges() {
+        assert!(RangeSet::from(5..5).is_empty());
+        let (start, end) = (7, 3);
+        assert!(RangeSet::from(start..end).is_empty());
+        assert_eq!(RangeSet::default().min(), None);
+    }
+
+    #[test]
+    fn union() {
+        let a = set(&[0..10, 20..30]);
+        assert_eq!(
+            ranges(&a.union(&set(&[5..12, 15..20, 40..50]))),
+            [0..12, 15..30, 40..50]
+        );
+        assert_eq!(a.union(&RangeSet::default()), a);
+        assert_eq!(RangeSet::default().union(&a), a);
+    }
+
+    #[test]
+    fn intersection() {
+        let a = set(&[0..10, 20..30]);
+        let b = set(&[5..25, 28..40]);
+        assert_eq!(ranges(&a.intersection(&b)), [5..10, 20..25, 28..30]);
+        assert!(a.intersection(&RangeSet::from(10..20)).is_empty());
+    }
+
+    #[test]
+    fn difference() {
+        let a = set(&[0..10, 20..30]);
+
+        // a single range cutting into both ranges
+        assert_eq!(
+            ranges(&a.difference(&RangeSet::from(5..25))),
+            [0..5, 25..30]
+        );
+        // several ranges cutting a single range
+        assert_eq!(
+            ranges(&a.difference(&set(&[1..2, 4..6]))),
+            [0..1, 2..4, 6..10, 20..30]
+        );
+        // touching ranges remove nothing
+        assert_eq!(
+            ranges(&a.difference(&set(&[10..20, 30..40]))),
+            [0..10, 20..30]
+        );
+        // covering everything
+        assert!(a.difference(&RangeSet::from(0..30)).is_empty());
+    }
+
+    #[test]
+    fn split_at() {
+        let a = set(&[0..10, 20..30]);
+
+        let (below, above) = a.split_at(5);
+        assert_eq!(below, RangeSet::from(0..5));
+        assert_eq!(above, set(&[5..10, 20..30]));
+
+        // splitting at the start of a range, or between ranges, doesn't cut anything
+        for point in [15, 20] {
+            let (below, above) = a.split_at(point);
+            assert_eq!(below, RangeSet::from(0..10));
+            assert_eq!(above, RangeSet::from(20..30));
+        }
+
+        let (below, above) = a.split_at(0);
+        assert!(below.is_empty());
+        assert_eq!(above, a);
+
+        let (below, above) = a.split_at(30);
+        assert_eq!(below, a);
+        assert!(above.is_empty());
+    }
+
+    #[test]
+    fn map_offsets() {
+        let a = set(&[0..10, 20..30]);
+
+        // the first matching mapping wins and unmapped values stay in place
+        let mapped = a.map_offsets([(5..25, 100), (0..30, 1000)]);
+        assert_eq!(
+            ranges(&mapped),
+            [105..110, 120..125, 1000..1005, 1025..1030]
+        );
+
+        let b = set(&[10..20, 30..40]);
+        let mapped = b.map_offsets([(10..20, -5), (35..40, -10)]);
+        assert_eq!(ranges(&mapped), [5..15, 25..35]);
+
+        // mapped ranges are merged with the values next to them
+        let mapped = a.map_offsets([(20..30, -10)]);
+        assert_eq!(mapped, RangeSet::from(0..20));
+    }
+}